@@ -0,0 +1,103 @@
+use crate::stats::{AllocatorStats, Stats};
+use crate::{Grow, Locked};
+use core::alloc::{GlobalAlloc, Layout};
+use core::fmt::{self, Write};
+use core::ptr::null_mut;
+
+pub struct BumpAllocator {
+    heap_start: usize,
+    heap_end: usize,
+    allocations: usize,
+    next: usize,
+    grow: Option<Grow>,
+    stats: Stats,
+}
+
+impl BumpAllocator {
+    pub const fn new() -> Self {
+        BumpAllocator {
+            heap_start: 0,
+            heap_end: 0,
+            allocations: 0,
+            next: 0,
+            grow: None,
+            stats: Stats::new(),
+        }
+    }
+
+    pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        self.heap_start = heap_start;
+        self.heap_end = heap_start + heap_size;
+        self.allocations = 0;
+        self.next = heap_start;
+    }
+
+    /// Sets the hook called to extend the heap when it's full.
+    pub fn set_grow(&mut self, grow: Grow) {
+        self.grow = Some(grow);
+    }
+
+    fn alloc(&mut self, layout: &Layout) -> *mut u8 {
+        let alloc_start = crate::align_up(self.next, layout.align());
+        let alloc_end = match alloc_start.checked_add(layout.size()) {
+            Some(end) => end,
+            None => return null_mut(),
+        };
+
+        if alloc_end >= self.heap_end {
+            // try extending the heap, which is always contiguous with the current one
+            match self
+                .grow
+                .and_then(|grow| grow(alloc_end - self.heap_end + 1))
+            {
+                Some((start, size)) => {
+                    debug_assert_eq!(start, self.heap_end);
+                    self.heap_end += size;
+                }
+                None => return null_mut(),
+            }
+        }
+
+        self.next = alloc_end;
+        self.allocations += 1;
+        alloc_start as *mut u8
+    }
+}
+
+unsafe impl GlobalAlloc for Locked<BumpAllocator> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut alloc = self.lock();
+        let ptr = alloc.alloc(&layout);
+        alloc.stats.record_alloc(&layout, ptr);
+        ptr
+    }
+
+    unsafe fn dealloc(&self, _: *mut u8, layout: Layout) {
+        let mut alloc = self.lock();
+        alloc.stats.record_dealloc(&layout);
+        alloc.allocations -= 1;
+        if alloc.allocations == 0 {
+            alloc.next = alloc.heap_start;
+        }
+    }
+}
+
+impl AllocatorStats for Locked<BumpAllocator> {
+    fn stats(&self) -> Stats {
+        self.lock().stats
+    }
+
+    fn dump_heap(&self, out: &mut dyn Write) -> fmt::Result {
+        let alloc = self.lock();
+        writeln!(
+            out,
+            "bump heap {:#x}-{:#x}: {:#x}-{:#x} used by {} allocations, {} bytes left",
+            alloc.heap_start,
+            alloc.heap_end,
+            alloc.heap_start,
+            alloc.next,
+            alloc.allocations,
+            alloc.heap_end - alloc.next
+        )
+    }
+}
@@ -0,0 +1,251 @@
+use crate::stats::{AllocatorStats, SizeClass, Stats};
+use core::alloc::{GlobalAlloc, Layout};
+use core::fmt::{self, Write};
+use core::mem::{align_of, size_of};
+use core::ptr::null_mut;
+use spin::Mutex;
+
+// Guard bytes written on both sides of every allocation.
+const RED_ZONE: usize = 16;
+const GUARD_BYTE: u8 = 0xfd;
+// Fills memory that was just allocated, and memory that was just freed.
+const ALLOCATED_BYTE: u8 = 0xcd;
+const POISON_BYTE: u8 = 0x6b;
+
+/// Number of freed allocations that are held back from the wrapped allocator, so that writes to
+/// them can be caught before their memory is reused.
+pub const QUARANTINE: usize = 32;
+
+const ALIVE: u64 = 0xa11c_a7ed_a11c_a7ed;
+const FREED: u64 = 0xdead_f4ee_dead_f4ee;
+
+// Precedes the front red zone of every allocation.
+#[repr(C)]
+struct Header {
+    // links the live allocations
+    prev: *mut Header,
+    next: *mut Header,
+    size: usize,
+    align: usize,
+    // last, so that the wrapped allocator's bookkeeping is unlikely to overwrite it once freed
+    magic: u64,
+}
+
+// A freed allocation whose memory hasn't been handed back yet.
+#[derive(Clone, Copy)]
+struct Quarantined {
+    ptr: *mut u8,
+    layout: Layout,
+}
+
+struct Live {
+    head: *mut Header,
+    stats: Stats,
+    // a ring of the last freed allocations, `next` is the oldest one
+    quarantine: [Option<Quarantined>; QUARANTINE],
+    next: usize,
+}
+
+// The headers are only accessed with the lock held.
+unsafe impl Send for Live {}
+
+/// Wraps an allocator to catch heap corruption as early as possible.
+///
+/// Every allocation is surrounded by red zones that are checked when it's freed, and live
+/// allocations are linked together so that freeing something that isn't live, or with a different
+/// layout than it was allocated with, panics right away.
+///
+/// Freed memory is poisoned and quarantined: it's only handed back to the wrapped allocator, and
+/// so reused, once `QUARANTINE` other allocations were freed, and the poison is checked then to
+/// catch uses after free.
+pub struct DebugAllocator<A> {
+    inner: A,
+    live: Mutex<Live>,
+}
+
+impl<A> DebugAllocator<A> {
+    pub const fn new(inner: A) -> Self {
+        DebugAllocator {
+            inner,
+            live: Mutex::new(Live {
+                head: null_mut(),
+                stats: Stats::new(),
+                quarantine: [None; QUARANTINE],
+                next: 0,
+            }),
+        }
+    }
+
+    /// The wrapped allocator.
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Panics if the red zones of any live allocation, or quarantined memory, were overwritten.
+    pub fn check(&self) {
+        let live = self.live.lock();
+        let mut header = live.head;
+        while !header.is_null() {
+            unsafe {
+                Self::check_red_zones(&*header);
+                header = (*header).next;
+            }
+        }
+        for quarantined in live.quarantine.iter().flatten() {
+            unsafe { Self::check_poison(quarantined) };
+        }
+    }
+
+    // Offset of the caller's memory from the start of the wrapped allocation.
+    fn front(layout: &Layout) -> usize {
+        crate::align_up(
+            size_of::<Header>() + RED_ZONE,
+            layout.align().max(align_of::<Header>()),
+        )
+    }
+
+    fn outer_layout(layout: &Layout) -> Layout {
+        let size = Self::front(layout) + layout.size() + RED_ZONE;
+        Layout::from_size_align(size, layout.align().max(align_of::<Header>())).unwrap()
+    }
+
+    fn header(ptr: *mut u8) -> *mut Header {
+        (ptr as usize - RED_ZONE - size_of::<Header>()) as *mut Header
+    }
+
+    unsafe fn check_poison(quarantined: &Quarantined) {
+        let Quarantined { ptr, layout } = *quarantined;
+        let header = &*Self::header(ptr);
+        if header.magic != FREED {
+            panic!(
+                "use after free: the header of {:p} ({:?}) was overwritten after it was freed",
+                ptr, layout
+            );
+        }
+        if let Some(i) = (0..layout.size()).find(|&i| *ptr.add(i) != POISON_BYTE) {
+            panic!(
+                "use after free: {:p} ({:?}) was written at {:p} after it was freed",
+                ptr,
+                layout,
+                ptr.add(i)
+            );
+        }
+        Self::check_red_zones(header);
+    }
+
+    unsafe fn check_red_zones(header: &Header) {
+        let ptr = (header as *const Header as *const u8).add(size_of::<Header>() + RED_ZONE);
+        let zones = [
+            ("before", ptr.sub(RED_ZONE)),
+            ("after", ptr.add(header.size)),
+        ];
+        for &(side, zone) in zones.iter() {
+            if let Some(i) = (0..RED_ZONE).find(|&i| *zone.add(i) != GUARD_BYTE) {
+                panic!(
+                    "heap corruption: red zone {} {:p} ({} bytes) overwritten at {:p}",
+                    side,
+                    ptr,
+                    header.size,
+                    zone.add(i)
+                );
+            }
+        }
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for DebugAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut live = self.live.lock();
+        let base = self.inner.alloc(Self::outer_layout(&layout));
+        if base.is_null() {
+            live.stats.record_alloc(&layout, base);
+            return base;
+        }
+        let ptr = base.add(Self::front(&layout));
+
+        let header = Self::header(ptr);
+        header.write(Header {
+            prev: null_mut(),
+            next: live.head,
+            size: layout.size(),
+            align: layout.align(),
+            magic: ALIVE,
+        });
+        if !live.head.is_null() {
+            (*live.head).prev = header;
+        }
+        live.head = header;
+        live.stats.record_alloc(&layout, ptr);
+
+        ptr.sub(RED_ZONE).write_bytes(GUARD_BYTE, RED_ZONE);
+        ptr.add(layout.size()).write_bytes(GUARD_BYTE, RED_ZONE);
+        ptr.write_bytes(ALLOCATED_BYTE, layout.size());
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let mut live = self.live.lock();
+        let header = &mut *Self::header(ptr);
+        match header.magic {
+            ALIVE => {}
+            FREED => panic!("double free of {:p} ({:?})", ptr, layout),
+            _ => panic!(
+                "freeing {:p} ({:?}) which isn't allocated, or whose header was overwritten",
+                ptr, layout
+            ),
+        }
+        if header.size != layout.size() || header.align != layout.align() {
+            panic!(
+                "freeing {:p} with {:?}, but it was allocated with size {} and align {}",
+                ptr, layout, header.size, header.align
+            );
+        }
+        Self::check_red_zones(header);
+
+        if header.prev.is_null() {
+            live.head = header.next;
+        } else {
+            (*header.prev).next = header.next;
+        }
+        if !header.next.is_null() {
+            (*header.next).prev = header.prev;
+        }
+        header.magic = FREED;
+        live.stats.record_dealloc(&layout);
+
+        ptr.write_bytes(POISON_BYTE, layout.size());
+        let next = live.next;
+        live.next = (next + 1) % QUARANTINE;
+        let quarantined = Quarantined { ptr, layout };
+        if let Some(oldest) = live.quarantine[next].replace(quarantined) {
+            // about to be reused
+            Self::check_poison(&oldest);
+            self.inner.dealloc(
+                oldest.ptr.sub(Self::front(&oldest.layout)),
+                Self::outer_layout(&oldest.layout),
+            );
+        }
+    }
+}
+
+// Sizes are the ones requested by callers, the size classes are the wrapped allocator's.
+impl<A: AllocatorStats> AllocatorStats for DebugAllocator<A> {
+    fn stats(&self) -> Stats {
+        self.live.lock().stats
+    }
+
+    fn size_classes(&self, f: &mut dyn FnMut(SizeClass)) {
+        self.inner.size_classes(f)
+    }
+
+    fn dump_heap(&self, out: &mut dyn Write) -> fmt::Result {
+        self.check();
+        let inner = self.inner.stats();
+        writeln!(
+            out,
+            "debug allocator: {} bytes in use including red zones, headers and quarantine",
+            inner.bytes_in_use
+        )?;
+        self.inner.dump_heap(out)
+    }
+}
@@ -0,0 +1,166 @@
+use crate::stats::{AllocatorStats, SizeClass, Stats};
+use crate::{Grow, Locked};
+use core::alloc::{GlobalAlloc, Layout};
+use core::fmt::{self, Write};
+use core::mem;
+use core::ptr::{null_mut, NonNull};
+
+const BLOCK_SIZES: &[usize] = &[8, 16, 32, 64, 128, 256, 512, 1024, 2048];
+
+fn block_list_index(layout: &Layout) -> Option<usize> {
+    let size = layout.size().max(layout.align());
+    BLOCK_SIZES.iter().position(|&b| b > size)
+}
+
+struct Node {
+    next: Option<&'static mut Node>,
+}
+
+impl Node {
+    fn new() -> Self {
+        Node { next: None }
+    }
+}
+
+pub struct FixedAllocator {
+    heads: [Option<&'static mut Node>; BLOCK_SIZES.len()],
+    fallback: linked_list_allocator::Heap,
+    // blocks handed out and blocks waiting in the lists, per size
+    in_use: [usize; BLOCK_SIZES.len()],
+    free: [usize; BLOCK_SIZES.len()],
+    grow: Option<Grow>,
+    stats: Stats,
+}
+
+impl FixedAllocator {
+    pub const fn new() -> Self {
+        FixedAllocator {
+            heads: [None; BLOCK_SIZES.len()],
+            fallback: linked_list_allocator::Heap::empty(),
+            in_use: [0; BLOCK_SIZES.len()],
+            free: [0; BLOCK_SIZES.len()],
+            grow: None,
+            stats: Stats::new(),
+        }
+    }
+
+    pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        self.fallback.init(heap_start, heap_size);
+    }
+
+    /// Sets the hook called to extend the heap when it's full.
+    pub fn set_grow(&mut self, grow: Grow) {
+        self.grow = Some(grow);
+    }
+
+    fn fallback_alloc(&mut self, layout: Layout) -> *mut u8 {
+        if let Ok(ptr) = self.fallback.allocate_first_fit(layout) {
+            return ptr.as_ptr();
+        }
+        // the heap is full, try extending it before giving up
+        match self
+            .grow
+            .and_then(|grow| grow(layout.size() + layout.align()))
+        {
+            Some((start, size)) => {
+                debug_assert_eq!(start, self.fallback.top());
+                unsafe { self.fallback.extend(size) };
+                match self.fallback.allocate_first_fit(layout) {
+                    Ok(ptr) => ptr.as_ptr(),
+                    Err(_) => null_mut(),
+                }
+            }
+            None => null_mut(),
+        }
+    }
+}
+
+unsafe impl GlobalAlloc for Locked<FixedAllocator> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut alloc = self.lock();
+        let ptr = match block_list_index(&layout) {
+            Some(idx) => {
+                let ptr = match alloc.heads[idx].take() {
+                    Some(blk) => {
+                        alloc.heads[idx] = blk.next.take();
+                        alloc.free[idx] -= 1;
+                        blk as *mut Node as *mut u8
+                    }
+                    None => {
+                        let block_size = BLOCK_SIZES[idx];
+                        let block_align = block_size;
+                        let layout = Layout::from_size_align(block_size, block_align).unwrap();
+                        alloc.fallback_alloc(layout)
+                    }
+                };
+                if !ptr.is_null() {
+                    alloc.in_use[idx] += 1;
+                }
+                ptr
+            }
+            None => alloc.fallback_alloc(layout),
+        };
+        alloc.stats.record_alloc(&layout, ptr);
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let mut alloc = self.lock();
+        alloc.stats.record_dealloc(&layout);
+        match block_list_index(&layout) {
+            Some(idx) => {
+                let mut node = Node::new();
+                node.next = alloc.heads[idx].take();
+
+                assert!(mem::size_of::<Node>() <= BLOCK_SIZES[idx]);
+                assert!(mem::align_of::<Node>() <= BLOCK_SIZES[idx]);
+
+                let node_ptr = ptr as *mut Node;
+                node_ptr.write(node);
+                alloc.heads[idx] = Some(&mut *node_ptr);
+                alloc.in_use[idx] -= 1;
+                alloc.free[idx] += 1;
+            }
+            None => alloc
+                .fallback
+                .deallocate(NonNull::new(ptr).unwrap(), layout),
+        }
+    }
+}
+
+impl AllocatorStats for Locked<FixedAllocator> {
+    fn stats(&self) -> Stats {
+        self.lock().stats
+    }
+
+    fn size_classes(&self, f: &mut dyn FnMut(SizeClass)) {
+        let alloc = self.lock();
+        for (idx, &block_size) in BLOCK_SIZES.iter().enumerate() {
+            f(SizeClass {
+                block_size,
+                in_use: alloc.in_use[idx],
+                free: alloc.free[idx],
+            });
+        }
+    }
+
+    fn dump_heap(&self, out: &mut dyn Write) -> fmt::Result {
+        let alloc = self.lock();
+        writeln!(
+            out,
+            "fixed heap {:#x}-{:#x}: {} bytes used, {} bytes free",
+            alloc.fallback.bottom(),
+            alloc.fallback.top(),
+            alloc.fallback.used(),
+            alloc.fallback.free()
+        )?;
+        for (idx, &block_size) in BLOCK_SIZES.iter().enumerate() {
+            writeln!(
+                out,
+                "  {:>5} bytes blocks: {} in use, {} free",
+                block_size, alloc.in_use[idx], alloc.free[idx]
+            )?;
+        }
+        Ok(())
+    }
+}
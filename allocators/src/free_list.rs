@@ -0,0 +1,272 @@
+use crate::stats::{AllocatorStats, Stats};
+use crate::{Grow, Locked};
+use core::alloc::{GlobalAlloc, Layout};
+use core::fmt::{self, Write};
+use core::mem;
+use core::ptr::null_mut;
+
+struct Node {
+    size: usize,
+    next: Option<&'static mut Node>,
+}
+
+impl Node {
+    const fn new(size: usize) -> Self {
+        Node { size, next: None }
+    }
+
+    fn start_addr(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    fn end_addr(&self) -> usize {
+        self.start_addr() + self.size
+    }
+}
+
+/// How a free region is chosen for an allocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FitStrategy {
+    /// Use the first region, by address, that can hold the allocation.
+    FirstFit,
+    /// Use the smallest region that can hold the allocation.
+    BestFit,
+}
+
+/// Allocator keeping free regions in a list sorted by address.
+///
+/// Freed regions are merged with their neighbours so that the heap doesn't fragment over time.
+pub struct FreeListAllocator {
+    head: Node,
+    strategy: FitStrategy,
+    // bounds of the memory managed by the allocator
+    bottom: usize,
+    top: usize,
+    grow: Option<Grow>,
+    stats: Stats,
+}
+
+impl FreeListAllocator {
+    pub const fn new() -> Self {
+        FreeListAllocator::with_strategy(FitStrategy::FirstFit)
+    }
+
+    pub const fn with_strategy(strategy: FitStrategy) -> Self {
+        FreeListAllocator {
+            head: Node::new(0),
+            strategy,
+            bottom: 0,
+            top: 0,
+            grow: None,
+            stats: Stats::new(),
+        }
+    }
+
+    pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        self.bottom = heap_start;
+        self.top = heap_start + heap_size;
+        self.add_free_region(heap_start, heap_size);
+    }
+
+    /// Sets the hook called to extend the heap when it's full.
+    pub fn set_grow(&mut self, grow: Grow) {
+        self.grow = Some(grow);
+    }
+
+    pub fn set_strategy(&mut self, strategy: FitStrategy) {
+        self.strategy = strategy;
+    }
+
+    unsafe fn add_free_region(&mut self, addr: usize, size: usize) {
+        // ensure that the freed region is capable of holding Node
+        assert_eq!(crate::align_up(addr, mem::align_of::<Node>()), addr);
+        assert!(size >= mem::size_of::<Node>());
+
+        // find the last region before the freed one
+        let mut current = &mut self.head;
+        while current
+            .next
+            .as_ref()
+            .map_or(false, |next| next.start_addr() < addr)
+        {
+            current = current.next.as_mut().unwrap();
+        }
+        debug_assert!(
+            current.size == 0 || current.end_addr() <= addr,
+            "freed region overlaps a free region"
+        );
+
+        // the head is never merged since it has no size
+        if current.size > 0 && current.end_addr() == addr {
+            current.size += size;
+        } else {
+            let mut node = Node::new(size);
+            node.next = current.next.take();
+            let node_ptr = addr as *mut Node;
+            node_ptr.write(node);
+            current.next = Some(&mut *node_ptr);
+            current = current.next.as_mut().unwrap();
+        }
+
+        let end = current.end_addr();
+        if let Some(next) = current.next.as_mut() {
+            debug_assert!(
+                next.start_addr() >= end,
+                "freed region overlaps a free region"
+            );
+            if next.start_addr() == end {
+                current.size += next.size;
+                current.next = next.next.take();
+            }
+        }
+    }
+
+    fn find_region(&mut self, size: usize, align: usize) -> Option<(&'static mut Node, usize)> {
+        let strategy = self.strategy;
+        // the region preceding the chosen one, and where the allocation starts in the chosen one
+        let mut chosen: Option<(*mut Node, usize)> = None;
+        let mut chosen_size = usize::MAX;
+        let mut current: *mut Node = &mut self.head;
+        unsafe {
+            while let Some(node) = (*current).next.as_mut() {
+                if let Ok(alloc_start) = Self::align_from_region(node, size, align) {
+                    if node.size < chosen_size {
+                        chosen = Some((current, alloc_start));
+                        chosen_size = node.size;
+                    }
+                    if strategy == FitStrategy::FirstFit || node.size == size {
+                        break;
+                    }
+                }
+                current = &mut **node;
+            }
+
+            // unlink
+            let (previous, alloc_start) = chosen?;
+            let found = (*previous).next.take().unwrap();
+            (*previous).next = found.next.take();
+            Some((found, alloc_start))
+        }
+    }
+
+    fn align_from_region(region: &Node, size: usize, align: usize) -> Result<usize, ()> {
+        let mut alloc_start = crate::align_up(region.start_addr(), align);
+        let gap = alloc_start - region.start_addr();
+        // the space left in front of the allocation must be able to hold a Node as well
+        if gap > 0 && gap < mem::size_of::<Node>() {
+            alloc_start = crate::align_up(region.start_addr() + mem::size_of::<Node>(), align);
+        }
+        let alloc_end = alloc_start.checked_add(size).ok_or(())?;
+
+        if alloc_end > region.end_addr() {
+            return Err(());
+        }
+
+        let excess = region.end_addr() - alloc_end;
+        // if not perfect fit, we must have enough room to store a Node
+        if excess > 0 && excess < mem::size_of::<Node>() {
+            return Err(());
+        }
+
+        Ok(alloc_start)
+    }
+
+    fn size_align(layout: Layout) -> (usize, usize) {
+        let layout = layout
+            .align_to(mem::align_of::<Node>())
+            .expect("cannot align")
+            .pad_to_align();
+
+        let size = layout.size().max(mem::size_of::<Node>());
+        (size, layout.align())
+    }
+}
+
+unsafe impl GlobalAlloc for Locked<FreeListAllocator> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let (size, align) = FreeListAllocator::size_align(layout);
+        let mut allocator = self.lock();
+
+        let mut found = allocator.find_region(size, align);
+        if found.is_none() {
+            // add a freshly mapped region to the list and retry
+            if let Some((start, grown)) = allocator.grow.and_then(|grow| grow(size + align)) {
+                allocator.top = allocator.top.max(start + grown);
+                allocator.add_free_region(start, grown);
+                found = allocator.find_region(size, align);
+            }
+        }
+
+        let ptr = if let Some((region, addr)) = found {
+            let region_start = region.start_addr();
+            let region_end = region.end_addr();
+            let alloc_end = addr.checked_add(size).expect("overflow");
+            if addr > region_start {
+                allocator.add_free_region(region_start, addr - region_start);
+            }
+            if region_end > alloc_end {
+                allocator.add_free_region(alloc_end, region_end - alloc_end);
+            }
+            addr as *mut u8
+        } else {
+            null_mut()
+        };
+        allocator.stats.record_alloc(&layout, ptr);
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let (size, _) = FreeListAllocator::size_align(layout);
+        let mut allocator = self.lock();
+        allocator.stats.record_dealloc(&layout);
+        allocator.add_free_region(ptr as usize, size);
+    }
+}
+
+impl AllocatorStats for Locked<FreeListAllocator> {
+    fn stats(&self) -> Stats {
+        self.lock().stats
+    }
+
+    fn dump_heap(&self, out: &mut dyn Write) -> fmt::Result {
+        let allocator = self.lock();
+        writeln!(
+            out,
+            "free list heap {:#x}-{:#x}, {:?}:",
+            allocator.bottom, allocator.top, allocator.strategy
+        )?;
+        // everything between two free regions is in use
+        let mut used_start = allocator.bottom;
+        let mut current = allocator.head.next.as_ref();
+        while let Some(region) = current {
+            if region.start_addr() > used_start {
+                writeln!(
+                    out,
+                    "  {:#x}-{:#x} used ({} bytes)",
+                    used_start,
+                    region.start_addr(),
+                    region.start_addr() - used_start
+                )?;
+            }
+            writeln!(
+                out,
+                "  {:#x}-{:#x} free ({} bytes)",
+                region.start_addr(),
+                region.end_addr(),
+                region.size
+            )?;
+            used_start = region.end_addr();
+            current = region.next.as_ref();
+        }
+        if allocator.top > used_start {
+            writeln!(
+                out,
+                "  {:#x}-{:#x} used ({} bytes)",
+                used_start,
+                allocator.top,
+                allocator.top - used_start
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,46 @@
+//! Heap allocators used by the kernel.
+//!
+//! They only manage the memory they are given, so they can be tested on the host as well, see the
+//! README.
+#![cfg_attr(not(test), no_std)]
+#![feature(const_in_array_repeat_expressions)] // https://os.phil-opp.com/allocator-designs/#implementation-2
+#![feature(const_mut_refs)] // https://os.phil-opp.com/allocator-designs/#implementation-1
+
+pub mod bump;
+pub mod debug;
+pub mod fixed;
+pub mod free_list;
+pub mod linked_list;
+pub mod stats;
+
+pub use stats::{AllocatorStats, SizeClass, Stats};
+
+/// Called by an allocator whose heap is full with the minimum number of bytes it needs.
+///
+/// Returns the start and size of memory added right after the end of the heap, if any.
+pub type Grow = fn(usize) -> Option<(usize, usize)>;
+
+pub struct Locked<A> {
+    value: spin::Mutex<A>,
+}
+
+impl<A> Locked<A> {
+    pub const fn new(value: A) -> Self {
+        Locked {
+            value: spin::Mutex::new(value),
+        }
+    }
+
+    pub fn lock(&self) -> spin::MutexGuard<A> {
+        self.value.lock()
+    }
+}
+
+pub fn align_up(addr: usize, align: usize) -> usize {
+    let r = addr % align;
+    if r == 0 {
+        addr
+    } else {
+        addr - r + align
+    }
+}
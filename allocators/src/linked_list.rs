@@ -0,0 +1,85 @@
+use crate::stats::{AllocatorStats, Stats};
+use crate::{Grow, Locked};
+use core::alloc::{GlobalAlloc, Layout};
+use core::fmt::{self, Write};
+use core::ptr::{null_mut, NonNull};
+use linked_list_allocator::Heap;
+
+/// Unlike linked_list_allocator::LockedHeap, this extends the heap when it's full.
+pub struct LinkedListAllocator {
+    heap: Heap,
+    grow: Option<Grow>,
+    stats: Stats,
+}
+
+impl LinkedListAllocator {
+    pub const fn new() -> Self {
+        LinkedListAllocator {
+            heap: Heap::empty(),
+            grow: None,
+            stats: Stats::new(),
+        }
+    }
+
+    pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        self.heap.init(heap_start, heap_size);
+    }
+
+    /// Sets the hook called to extend the heap when it's full.
+    pub fn set_grow(&mut self, grow: Grow) {
+        self.grow = Some(grow);
+    }
+
+    fn alloc(&mut self, layout: Layout) -> *mut u8 {
+        if let Ok(ptr) = self.heap.allocate_first_fit(layout) {
+            return ptr.as_ptr();
+        }
+        match self
+            .grow
+            .and_then(|grow| grow(layout.size() + layout.align()))
+        {
+            Some((start, size)) => {
+                debug_assert_eq!(start, self.heap.top());
+                unsafe { self.heap.extend(size) };
+                match self.heap.allocate_first_fit(layout) {
+                    Ok(ptr) => ptr.as_ptr(),
+                    Err(_) => null_mut(),
+                }
+            }
+            None => null_mut(),
+        }
+    }
+}
+
+unsafe impl GlobalAlloc for Locked<LinkedListAllocator> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let mut alloc = self.lock();
+        let ptr = alloc.alloc(layout);
+        alloc.stats.record_alloc(&layout, ptr);
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let mut alloc = self.lock();
+        alloc.stats.record_dealloc(&layout);
+        alloc.heap.deallocate(NonNull::new(ptr).unwrap(), layout)
+    }
+}
+
+impl AllocatorStats for Locked<LinkedListAllocator> {
+    fn stats(&self) -> Stats {
+        self.lock().stats
+    }
+
+    fn dump_heap(&self, out: &mut dyn Write) -> fmt::Result {
+        let alloc = self.lock();
+        writeln!(
+            out,
+            "linked list heap {:#x}-{:#x}: {} bytes used, {} bytes free",
+            alloc.heap.bottom(),
+            alloc.heap.top(),
+            alloc.heap.used(),
+            alloc.heap.free()
+        )
+    }
+}
@@ -0,0 +1,66 @@
+use core::alloc::Layout;
+use core::fmt::{self, Write};
+
+/// Usage counters of an allocator, sizes are the ones requested by callers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    pub bytes_in_use: usize,
+    /// Highest value reached by `bytes_in_use`.
+    pub peak_bytes_in_use: usize,
+    /// Total number of successful allocations.
+    pub allocations: usize,
+    pub deallocations: usize,
+    /// Number of allocations that returned null.
+    pub failed_allocations: usize,
+}
+
+impl Stats {
+    pub const fn new() -> Self {
+        Stats {
+            bytes_in_use: 0,
+            peak_bytes_in_use: 0,
+            allocations: 0,
+            deallocations: 0,
+            failed_allocations: 0,
+        }
+    }
+
+    pub fn live_allocations(&self) -> usize {
+        self.allocations - self.deallocations
+    }
+
+    pub(super) fn record_alloc(&mut self, layout: &Layout, ptr: *mut u8) {
+        if ptr.is_null() {
+            self.failed_allocations += 1;
+            return;
+        }
+        self.allocations += 1;
+        self.bytes_in_use += layout.size();
+        self.peak_bytes_in_use = self.peak_bytes_in_use.max(self.bytes_in_use);
+    }
+
+    pub(super) fn record_dealloc(&mut self, layout: &Layout) {
+        self.deallocations += 1;
+        self.bytes_in_use -= layout.size();
+    }
+}
+
+/// Occupancy of the blocks of one size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeClass {
+    pub block_size: usize,
+    pub in_use: usize,
+    /// Blocks that were freed and are kept for reuse.
+    pub free: usize,
+}
+
+/// Introspection of an allocator.
+pub trait AllocatorStats {
+    fn stats(&self) -> Stats;
+
+    /// Calls `f` with each size class, for allocators that have them.
+    fn size_classes(&self, _f: &mut dyn FnMut(SizeClass)) {}
+
+    /// Writes a map of the heap to `out`.
+    fn dump_heap(&self, out: &mut dyn Write) -> fmt::Result;
+}
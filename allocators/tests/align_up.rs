@@ -0,0 +1,29 @@
+use allocators::align_up;
+
+mod common;
+
+#[test]
+fn aligned_addresses_are_unchanged() {
+    assert_eq!(align_up(0, 8), 0);
+    assert_eq!(align_up(4096, 4096), 4096);
+    assert_eq!(align_up(24, 1), 24);
+}
+
+#[test]
+fn unaligned_addresses_are_rounded_up() {
+    assert_eq!(align_up(1, 8), 8);
+    assert_eq!(align_up(4097, 4096), 8192);
+    assert_eq!(align_up(15, 16), 16);
+}
+
+#[test]
+fn random_addresses() {
+    let mut rng = common::Rng::new(0);
+    for _ in 0..100_000 {
+        let addr = rng.below(usize::MAX / 2);
+        let align = 1 << rng.below(16);
+        let aligned = align_up(addr, align);
+        assert_eq!(aligned % align, 0);
+        assert!(aligned >= addr && aligned - addr < align);
+    }
+}
@@ -0,0 +1,77 @@
+use allocators::bump::BumpAllocator;
+use allocators::{AllocatorStats, Locked};
+use common::Arena;
+use std::alloc::{GlobalAlloc, Layout};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+mod common;
+
+const HEAP_SIZE: usize = 64 * 1024;
+
+fn allocator(arena: &Arena, size: usize) -> Locked<BumpAllocator> {
+    let allocator = Locked::new(BumpAllocator::new());
+    unsafe { allocator.lock().init(arena.start(), size) };
+    allocator
+}
+
+#[test]
+fn allocations_are_contiguous() {
+    let arena = Arena::new(HEAP_SIZE);
+    let allocator = allocator(&arena, HEAP_SIZE);
+    let layout = Layout::from_size_align(24, 8).unwrap();
+    let first = unsafe { allocator.alloc(layout) };
+    let second = unsafe { allocator.alloc(layout) };
+    assert_eq!(first as usize, arena.start());
+    assert_eq!(second as usize, arena.start() + 24);
+}
+
+#[test]
+fn allocations_fail_when_full() {
+    let arena = Arena::new(HEAP_SIZE);
+    let allocator = allocator(&arena, HEAP_SIZE);
+    let layout = Layout::from_size_align(HEAP_SIZE, 8).unwrap();
+    assert!(unsafe { allocator.alloc(layout) }.is_null());
+    assert_eq!(allocator.stats().failed_allocations, 1);
+}
+
+#[test]
+fn random_sequences() {
+    let arena = Arena::new(HEAP_SIZE);
+    for seed in 0..100 {
+        let allocator = allocator(&arena, HEAP_SIZE);
+        let sequence =
+            common::random_sequence(&allocator, (arena.start(), arena.end()), seed, 1000, 32);
+        for block in sequence.live {
+            common::free(&allocator, block);
+        }
+
+        // the heap starts over once everything is freed
+        let stats = allocator.stats();
+        assert_eq!(stats.bytes_in_use, 0);
+        assert_eq!(stats.live_allocations(), 0);
+        let layout = Layout::from_size_align(8, 8).unwrap();
+        assert_eq!(unsafe { allocator.alloc(layout) } as usize, arena.start());
+    }
+}
+
+static GROWTH: AtomicUsize = AtomicUsize::new(0);
+
+fn grow(_: usize) -> Option<(usize, usize)> {
+    match GROWTH.swap(0, Ordering::Relaxed) {
+        0 => None,
+        start => Some((start, HEAP_SIZE / 2)),
+    }
+}
+
+#[test]
+fn heap_grows_through_the_hook() {
+    let arena = Arena::new(HEAP_SIZE);
+    let allocator = allocator(&arena, HEAP_SIZE / 2);
+    allocator.lock().set_grow(grow);
+    GROWTH.store(arena.start() + HEAP_SIZE / 2, Ordering::Relaxed);
+
+    let layout = Layout::from_size_align(HEAP_SIZE / 2, 8).unwrap();
+    let ptr = unsafe { allocator.alloc(layout) };
+    assert_eq!(ptr as usize, arena.start());
+    assert_eq!(GROWTH.load(Ordering::Relaxed), 0);
+}
@@ -0,0 +1,160 @@
+// Helpers shared by the host tests, not every test uses all of them.
+#![allow(dead_code)]
+
+use std::alloc::{self, GlobalAlloc, Layout};
+
+/// Memory handed to an allocator under test.
+pub struct Arena {
+    ptr: *mut u8,
+    layout: Layout,
+}
+
+impl Arena {
+    pub fn new(size: usize) -> Self {
+        let layout = Layout::from_size_align(size, 4096).unwrap();
+        let ptr = unsafe { alloc::alloc(layout) };
+        assert!(!ptr.is_null());
+        Arena { ptr, layout }
+    }
+
+    pub fn start(&self) -> usize {
+        self.ptr as usize
+    }
+
+    pub fn size(&self) -> usize {
+        self.layout.size()
+    }
+
+    pub fn end(&self) -> usize {
+        self.start() + self.size()
+    }
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        unsafe { alloc::dealloc(self.ptr, self.layout) }
+    }
+}
+
+/// xorshift64*, so that a failing sequence can be replayed from its seed.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // the state must never be zero
+        Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// A number in [0, n).
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    /// Mostly small sizes, with the occasional large one, and alignments up to 64.
+    pub fn layout(&mut self) -> Layout {
+        let size = match self.below(8) {
+            0 => 1 + self.below(4096),
+            _ => 1 + self.below(256),
+        };
+        Layout::from_size_align(size, 1 << self.below(7)).unwrap()
+    }
+}
+
+pub struct Block {
+    pub ptr: *mut u8,
+    pub layout: Layout,
+    fill: u8,
+}
+
+impl Block {
+    fn overlaps(&self, other: &Block) -> bool {
+        let (start, end) = (self.ptr as usize, self.ptr as usize + self.layout.size());
+        let other_start = other.ptr as usize;
+        start < other_start + other.layout.size() && other_start < end
+    }
+
+    fn content(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.layout.size()) }
+    }
+}
+
+/// Outcome of `random_sequence`.
+pub struct Sequence {
+    pub failed: usize,
+    pub live: Vec<Block>,
+}
+
+/// Allocates and frees blocks in random order, checking that every block is aligned, within
+/// `bounds`, doesn't overlap any live block and keeps its content until it's freed.
+///
+/// Failed allocations are counted, not treated as errors. At most `max_live` blocks are live at
+/// once, the ones still live at the end are returned.
+pub fn random_sequence<A: GlobalAlloc>(
+    allocator: &A,
+    bounds: (usize, usize),
+    seed: u64,
+    iterations: usize,
+    max_live: usize,
+) -> Sequence {
+    let mut rng = Rng::new(seed);
+    let mut sequence = Sequence {
+        failed: 0,
+        live: Vec::new(),
+    };
+    for i in 0..iterations {
+        let live = &mut sequence.live;
+        if !live.is_empty() && (live.len() >= max_live || rng.below(3) == 0) {
+            let block = live.swap_remove(rng.below(live.len()));
+            free(allocator, block);
+            continue;
+        }
+
+        let layout = rng.layout();
+        let ptr = unsafe { allocator.alloc(layout) };
+        if ptr.is_null() {
+            sequence.failed += 1;
+            continue;
+        }
+        let block = Block {
+            ptr,
+            layout,
+            fill: i as u8,
+        };
+        let (start, end) = bounds;
+        assert_eq!(
+            ptr as usize % layout.align(),
+            0,
+            "seed {}: misaligned",
+            seed
+        );
+        assert!(
+            ptr as usize >= start && ptr as usize + layout.size() <= end,
+            "seed {}: {:p} is outside of the heap",
+            seed,
+            ptr
+        );
+        if let Some(other) = live.iter().find(|other| other.overlaps(&block)) {
+            panic!("seed {}: {:p} overlaps {:p}", seed, ptr, other.ptr);
+        }
+        unsafe { ptr.write_bytes(block.fill, layout.size()) };
+        live.push(block);
+    }
+    sequence
+}
+
+/// Checks that the block's content wasn't overwritten and frees it.
+pub fn free<A: GlobalAlloc>(allocator: &A, block: Block) {
+    assert!(
+        block.content().iter().all(|&b| b == block.fill),
+        "{:p} was overwritten",
+        block.ptr
+    );
+    unsafe { allocator.dealloc(block.ptr, block.layout) };
+}
@@ -0,0 +1,113 @@
+use allocators::debug::{DebugAllocator, QUARANTINE};
+use allocators::free_list::FreeListAllocator;
+use allocators::{AllocatorStats, Locked};
+use common::Arena;
+use std::alloc::{GlobalAlloc, Layout};
+
+mod common;
+
+const HEAP_SIZE: usize = 256 * 1024;
+
+fn allocator(arena: &Arena) -> DebugAllocator<Locked<FreeListAllocator>> {
+    let allocator = DebugAllocator::new(Locked::new(FreeListAllocator::new()));
+    unsafe { allocator.inner().lock().init(arena.start(), arena.size()) };
+    allocator
+}
+
+#[test]
+fn random_sequences() {
+    let arena = Arena::new(HEAP_SIZE);
+    for seed in 0..100 {
+        let allocator = allocator(&arena);
+        let sequence =
+            common::random_sequence(&allocator, (arena.start(), arena.end()), seed, 1000, 64);
+        assert_eq!(sequence.failed, 0, "seed {}", seed);
+        allocator.check();
+        for block in sequence.live {
+            common::free(&allocator, block);
+        }
+    }
+}
+
+#[test]
+fn freed_memory_is_poisoned() {
+    let arena = Arena::new(HEAP_SIZE);
+    let allocator = allocator(&arena);
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    let ptr = unsafe { allocator.alloc(layout) };
+    unsafe {
+        ptr.write_bytes(0, layout.size());
+        allocator.dealloc(ptr, layout);
+    }
+    // still quarantined, so it's safe to read
+    let freed = unsafe { std::slice::from_raw_parts(ptr, layout.size()) };
+    assert!(freed.iter().all(|&b| b == 0x6b));
+}
+
+#[test]
+fn stats_exclude_the_overhead() {
+    let arena = Arena::new(HEAP_SIZE);
+    let allocator = allocator(&arena);
+    let layout = Layout::from_size_align(40, 8).unwrap();
+    let ptr = unsafe { allocator.alloc(layout) };
+    assert_eq!(allocator.stats().bytes_in_use, 40);
+    assert!(allocator.inner().stats().bytes_in_use > 40);
+    unsafe { allocator.dealloc(ptr, layout) };
+    assert_eq!(allocator.stats().bytes_in_use, 0);
+}
+
+#[test]
+#[should_panic(expected = "double free")]
+fn double_free() {
+    let arena = Arena::new(HEAP_SIZE);
+    let allocator = allocator(&arena);
+    let layout = Layout::from_size_align(32, 8).unwrap();
+    unsafe {
+        let ptr = allocator.alloc(layout);
+        allocator.dealloc(ptr, layout);
+        allocator.dealloc(ptr, layout);
+    }
+}
+
+#[test]
+#[should_panic(expected = "allocated with size 32")]
+fn layout_mismatch() {
+    let arena = Arena::new(HEAP_SIZE);
+    let allocator = allocator(&arena);
+    let layout = Layout::from_size_align(32, 8).unwrap();
+    unsafe {
+        let ptr = allocator.alloc(layout);
+        allocator.dealloc(ptr, Layout::from_size_align(16, 8).unwrap());
+    }
+}
+
+#[test]
+#[should_panic(expected = "red zone after")]
+fn overflow() {
+    let arena = Arena::new(HEAP_SIZE);
+    let allocator = allocator(&arena);
+    let layout = Layout::from_size_align(32, 8).unwrap();
+    unsafe {
+        let ptr = allocator.alloc(layout);
+        ptr.add(32).write(0);
+        allocator.dealloc(ptr, layout);
+    }
+}
+
+#[test]
+#[should_panic(expected = "use after free")]
+fn use_after_free() {
+    let arena = Arena::new(HEAP_SIZE);
+    let allocator = allocator(&arena);
+    let layout = Layout::from_size_align(32, 8).unwrap();
+    unsafe {
+        let ptr = allocator.alloc(layout);
+        allocator.dealloc(ptr, layout);
+        ptr.add(8).write(0);
+        // push it out of the quarantine
+        for _ in 0..QUARANTINE {
+            let other = allocator.alloc(layout);
+            allocator.dealloc(other, layout);
+        }
+    }
+}
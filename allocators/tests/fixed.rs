@@ -0,0 +1,61 @@
+use allocators::fixed::FixedAllocator;
+use allocators::{AllocatorStats, Locked};
+use common::Arena;
+use std::alloc::{GlobalAlloc, Layout};
+
+mod common;
+
+const HEAP_SIZE: usize = 256 * 1024;
+
+fn allocator(arena: &Arena) -> Locked<FixedAllocator> {
+    let allocator = Locked::new(FixedAllocator::new());
+    unsafe { allocator.lock().init(arena.start(), arena.size()) };
+    allocator
+}
+
+#[test]
+fn freed_blocks_are_reused() {
+    let arena = Arena::new(HEAP_SIZE);
+    let allocator = allocator(&arena);
+    let layout = Layout::from_size_align(24, 8).unwrap();
+    let first = unsafe { allocator.alloc(layout) };
+    unsafe { allocator.dealloc(first, layout) };
+    assert_eq!(unsafe { allocator.alloc(layout) }, first);
+}
+
+#[test]
+fn size_classes_track_occupancy() {
+    let arena = Arena::new(HEAP_SIZE);
+    let allocator = allocator(&arena);
+    let layout = Layout::from_size_align(100, 8).unwrap();
+    let blocks = unsafe { [allocator.alloc(layout), allocator.alloc(layout)] };
+    unsafe { allocator.dealloc(blocks[1], layout) };
+
+    let mut class = None;
+    allocator.size_classes(&mut |c| {
+        if c.block_size == 128 {
+            class = Some(c);
+        }
+    });
+    let class = class.unwrap();
+    assert_eq!(class.in_use, 1);
+    assert_eq!(class.free, 1);
+}
+
+#[test]
+fn random_sequences() {
+    for seed in 0..200 {
+        // the blocks are never given back to the fallback allocator, start from a fresh heap
+        let arena = Arena::new(HEAP_SIZE);
+        let allocator = allocator(&arena);
+        let sequence =
+            common::random_sequence(&allocator, (arena.start(), arena.end()), seed, 2000, 64);
+        assert_eq!(sequence.failed, 0, "seed {}", seed);
+        for block in sequence.live {
+            common::free(&allocator, block);
+        }
+        let stats = allocator.stats();
+        assert_eq!(stats.bytes_in_use, 0);
+        assert_eq!(stats.allocations, stats.deallocations);
+    }
+}
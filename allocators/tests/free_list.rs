@@ -0,0 +1,143 @@
+use allocators::free_list::{FitStrategy, FreeListAllocator};
+use allocators::{AllocatorStats, Locked};
+use common::Arena;
+use std::alloc::{GlobalAlloc, Layout};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+mod common;
+
+const HEAP_SIZE: usize = 256 * 1024;
+
+fn allocator(arena: &Arena, size: usize, strategy: FitStrategy) -> Locked<FreeListAllocator> {
+    let allocator = Locked::new(FreeListAllocator::with_strategy(strategy));
+    unsafe { allocator.lock().init(arena.start(), size) };
+    allocator
+}
+
+fn random_sequences(strategy: FitStrategy) {
+    let arena = Arena::new(HEAP_SIZE);
+    for seed in 0..200 {
+        let allocator = allocator(&arena, HEAP_SIZE, strategy);
+        let sequence =
+            common::random_sequence(&allocator, (arena.start(), arena.end()), seed, 2000, 64);
+        assert_eq!(sequence.failed, 0, "seed {}", seed);
+        for block in sequence.live {
+            common::free(&allocator, block);
+        }
+
+        // everything was merged back into a single region
+        assert_eq!(allocator.stats().bytes_in_use, 0);
+        let everything = Layout::from_size_align(HEAP_SIZE, 8).unwrap();
+        let ptr = unsafe { allocator.alloc(everything) };
+        assert_eq!(ptr as usize, arena.start(), "seed {}", seed);
+    }
+}
+
+#[test]
+fn random_sequences_first_fit() {
+    random_sequences(FitStrategy::FirstFit);
+}
+
+#[test]
+fn random_sequences_best_fit() {
+    random_sequences(FitStrategy::BestFit);
+}
+
+#[test]
+fn heap_map_covers_the_heap() {
+    let arena = Arena::new(HEAP_SIZE);
+    let allocator = allocator(&arena, HEAP_SIZE, FitStrategy::FirstFit);
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    let blocks = unsafe { [allocator.alloc(layout), allocator.alloc(layout)] };
+    unsafe { allocator.dealloc(blocks[0], layout) };
+
+    let mut map = String::new();
+    allocator.dump_heap(&mut map).unwrap();
+    let lines: Vec<&str> = map.lines().collect();
+    assert_eq!(lines.len(), 4, "{}", map);
+    assert!(lines[1].ends_with("free (64 bytes)"), "{}", map);
+    assert!(lines[2].ends_with("used (64 bytes)"), "{}", map);
+    assert!(lines[3].contains("free"), "{}", map);
+}
+
+#[test]
+fn space_after_an_allocation_stays_in_the_heap() {
+    let arena = Arena::new(HEAP_SIZE);
+    let allocator = allocator(&arena, HEAP_SIZE, FitStrategy::FirstFit);
+    let small = Layout::from_size_align(64, 8).unwrap();
+    let first = unsafe { allocator.alloc(small) };
+    assert_eq!(first as usize, arena.start());
+
+    // the rest of the heap is free, and nothing past its end
+    let rest = Layout::from_size_align(HEAP_SIZE - 64, 8).unwrap();
+    let second = unsafe { allocator.alloc(rest) };
+    assert_eq!(second as usize, arena.start() + 64);
+    assert!(unsafe { allocator.alloc(small) }.is_null());
+}
+
+static GROWTH: AtomicUsize = AtomicUsize::new(0);
+
+fn grow(_: usize) -> Option<(usize, usize)> {
+    match GROWTH.swap(0, Ordering::Relaxed) {
+        0 => None,
+        start => Some((start, HEAP_SIZE / 2)),
+    }
+}
+
+#[test]
+fn heap_grows_through_the_hook() {
+    let arena = Arena::new(HEAP_SIZE);
+    let allocator = allocator(&arena, HEAP_SIZE / 2, FitStrategy::FirstFit);
+    allocator.lock().set_grow(grow);
+    GROWTH.store(arena.start() + HEAP_SIZE / 2, Ordering::Relaxed);
+
+    // only fits once the new memory is merged with the existing heap
+    let layout = Layout::from_size_align(HEAP_SIZE * 3 / 4, 8).unwrap();
+    let ptr = unsafe { allocator.alloc(layout) };
+    assert_eq!(ptr as usize, arena.start());
+    assert!(unsafe { allocator.alloc(layout) }.is_null());
+}
+
+#[test]
+fn best_fit_uses_the_smallest_region() {
+    let arena = Arena::new(HEAP_SIZE);
+    let allocator = allocator(&arena, HEAP_SIZE, FitStrategy::BestFit);
+    let large = Layout::from_size_align(256, 8).unwrap();
+    let small = Layout::from_size_align(64, 8).unwrap();
+    let blocks = unsafe {
+        [
+            allocator.alloc(small),
+            allocator.alloc(large),
+            allocator.alloc(small),
+            allocator.alloc(small),
+            allocator.alloc(small),
+        ]
+    };
+    // leave a 256 bytes hole followed by a 64 bytes one
+    unsafe {
+        allocator.dealloc(blocks[1], large);
+        allocator.dealloc(blocks[3], small);
+    }
+    assert_eq!(unsafe { allocator.alloc(small) }, blocks[3]);
+
+    allocator.lock().set_strategy(FitStrategy::FirstFit);
+    assert_eq!(unsafe { allocator.alloc(small) }, blocks[1]);
+}
+
+#[test]
+fn stats_count_requested_bytes() {
+    let arena = Arena::new(HEAP_SIZE);
+    let allocator = allocator(&arena, HEAP_SIZE, FitStrategy::FirstFit);
+    let small = Layout::from_size_align(24, 8).unwrap();
+    let large = Layout::from_size_align(200, 16).unwrap();
+    let blocks = unsafe { [allocator.alloc(small), allocator.alloc(large)] };
+    let stats = allocator.stats();
+    assert_eq!(stats.bytes_in_use, small.size() + large.size());
+    assert_eq!(stats.live_allocations(), 2);
+
+    unsafe { allocator.dealloc(blocks[0], small) };
+    let stats = allocator.stats();
+    assert_eq!(stats.bytes_in_use, large.size());
+    assert_eq!(stats.peak_bytes_in_use, small.size() + large.size());
+    assert_eq!(stats.deallocations, 1);
+}
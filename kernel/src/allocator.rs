@@ -0,0 +1,256 @@
+use crate::memory::vmm;
+use core::fmt::{self, Write};
+use core::ops::DerefMut;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
+use x86_64::structures::paging::mapper::MapToError;
+use x86_64::structures::paging::{
+    FrameAllocator, FrameDeallocator, Mapper, Page, PageSize, PageTableFlags, Size4KiB,
+};
+use x86_64::VirtAddr;
+
+pub mod slab;
+
+// The allocators live in their own crate so that they can be tested on the host.
+pub use allocators::{
+    align_up, bump, debug, fixed, free_list, linked_list, stats, AllocatorStats, Locked, SizeClass,
+    Stats,
+};
+
+// The global allocator is selected by the alloc-* cargo features.
+#[cfg(not(any(
+    feature = "alloc-bump",
+    feature = "alloc-fixed",
+    feature = "alloc-free-list",
+    feature = "alloc-linked-list"
+)))]
+compile_error!("one of the alloc-* features must be enabled to select the global allocator");
+
+#[cfg(any(
+    all(feature = "alloc-bump", feature = "alloc-fixed"),
+    all(feature = "alloc-bump", feature = "alloc-free-list"),
+    all(feature = "alloc-bump", feature = "alloc-linked-list"),
+    all(feature = "alloc-fixed", feature = "alloc-free-list"),
+    all(feature = "alloc-fixed", feature = "alloc-linked-list"),
+    all(feature = "alloc-free-list", feature = "alloc-linked-list")
+))]
+compile_error!("only one of the alloc-* features can be enabled, use --no-default-features");
+
+#[cfg(feature = "alloc-bump")]
+type Selected = Locked<bump::BumpAllocator>;
+#[cfg(feature = "alloc-bump")]
+const fn new_selected() -> Selected {
+    Locked::new(bump::BumpAllocator::new())
+}
+
+#[cfg(feature = "alloc-fixed")]
+type Selected = Locked<fixed::FixedAllocator>;
+#[cfg(feature = "alloc-fixed")]
+const fn new_selected() -> Selected {
+    Locked::new(fixed::FixedAllocator::new())
+}
+
+#[cfg(feature = "alloc-free-list")]
+type Selected = Locked<free_list::FreeListAllocator>;
+#[cfg(feature = "alloc-free-list")]
+const fn new_selected() -> Selected {
+    Locked::new(free_list::FreeListAllocator::new())
+}
+
+#[cfg(feature = "alloc-linked-list")]
+type Selected = Locked<linked_list::LinkedListAllocator>;
+#[cfg(feature = "alloc-linked-list")]
+const fn new_selected() -> Selected {
+    Locked::new(linked_list::LinkedListAllocator::new())
+}
+
+// alloc-debug wraps the selected allocator in a DebugAllocator.
+#[cfg(not(feature = "alloc-debug"))]
+#[global_allocator]
+static ALLOCATOR: Selected = new_selected();
+
+#[cfg(feature = "alloc-debug")]
+#[global_allocator]
+static ALLOCATOR: debug::DebugAllocator<Selected> = debug::DebugAllocator::new(new_selected());
+
+#[cfg(not(feature = "alloc-debug"))]
+fn selected() -> &'static Selected {
+    &ALLOCATOR
+}
+
+#[cfg(feature = "alloc-debug")]
+fn selected() -> &'static Selected {
+    ALLOCATOR.inner()
+}
+
+pub const HEAP_SIZE: usize = 1024 * 1024; // 1MiB
+/// Default value of the heap's ceiling, see `set_heap_limit`.
+pub const HEAP_LIMIT: usize = 16 * 1024 * 1024; // 16MiB
+
+// The heap's virtual memory, only [start, end) is mapped.
+struct HeapRegion {
+    start: usize,
+    end: usize,
+    reserved: usize,
+}
+
+static HEAP: Mutex<HeapRegion> = Mutex::new(HeapRegion {
+    start: 0,
+    end: 0,
+    reserved: 0,
+});
+static LIMIT: AtomicUsize = AtomicUsize::new(HEAP_LIMIT);
+
+/// Why heap memory couldn't be mapped.
+#[derive(Debug)]
+pub enum HeapError {
+    Map(MapToError<Size4KiB>),
+    /// The memory module's locks are held, by the code that the allocator was called from.
+    Contended,
+}
+
+impl From<MapToError<Size4KiB>> for HeapError {
+    fn from(error: MapToError<Size4KiB>) -> Self {
+        HeapError::Map(error)
+    }
+}
+
+pub fn init() -> Result<(), HeapError> {
+    let mut heap = HEAP.lock();
+    let mut vmm = vmm::VMM.lock();
+    // reserve the whole region so that the heap can grow contiguously
+    let reserved = vmm.region(vmm::HEAP).expect("heap region not reserved");
+    let page_range = vmm
+        .allocate(vmm::HEAP, reserved.end - reserved.start)
+        .expect("failed to allocate the heap's virtual memory");
+    heap.start = page_range.start.start_address().as_u64() as usize;
+    heap.end = heap.start;
+    heap.reserved = page_range.end.start_address().as_u64() as usize;
+
+    map_pages(heap.end, HEAP_SIZE)?;
+    heap.end += HEAP_SIZE;
+
+    unsafe {
+        let mut allocator = selected().lock();
+        allocator.init(heap.start, HEAP_SIZE);
+        allocator.set_grow(grow);
+    }
+
+    Ok(())
+}
+
+/// Sets the size up to which the heap may grow.
+///
+/// The heap never shrinks, so lowering the limit below the current size only prevents further
+/// growth. The limit is capped by the size of the heap's virtual memory region.
+pub fn set_heap_limit(limit: usize) {
+    LIMIT.store(limit, Ordering::Relaxed);
+}
+
+/// Current size of the heap, in bytes.
+pub fn heap_size() -> usize {
+    let heap = HEAP.lock();
+    heap.end - heap.start
+}
+
+/// Usage counters of the global allocator.
+pub fn stats() -> Stats {
+    ALLOCATOR.stats()
+}
+
+/// Occupancy of the global allocator's size classes, if it has any.
+pub fn size_classes(f: &mut dyn FnMut(SizeClass)) {
+    ALLOCATOR.size_classes(f)
+}
+
+/// Writes the global allocator's counters, then its heap layout.
+///
+/// `out` must not allocate, the allocator is locked while its layout is written.
+pub fn write_heap(out: &mut dyn Write) -> fmt::Result {
+    let stats = ALLOCATOR.stats();
+    writeln!(
+        out,
+        "heap: {} bytes in use (peak {}), {} live allocations, {} allocations, {} failed",
+        stats.bytes_in_use,
+        stats.peak_bytes_in_use,
+        stats.live_allocations(),
+        stats.allocations,
+        stats.failed_allocations
+    )?;
+    ALLOCATOR.dump_heap(out)
+}
+
+/// Prints the global allocator's counters and heap layout to the serial port.
+pub fn dump_heap() {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        write_heap(&mut *crate::serial::SERIAL.lock()).expect("failed writing to serial interface")
+    });
+}
+
+/// Maps at least `min_size` bytes of memory right after the end of the heap.
+///
+/// Returns the start and size of the newly mapped memory, or `None` when the heap has reached its
+/// limit or the memory can't be mapped. The memory module's locks are only tried, since this is
+/// called with the global allocator's lock held.
+fn grow(min_size: usize) -> Option<(usize, usize)> {
+    let mut heap = HEAP.try_lock()?;
+    let size = align_up(min_size.max(HEAP_SIZE), Size4KiB::SIZE as usize);
+    let limit = (heap.start + LIMIT.load(Ordering::Relaxed)).min(heap.reserved);
+    if heap.end.checked_add(size)? > limit {
+        return None;
+    }
+    map_pages(heap.end, size).ok()?;
+    let start = heap.end;
+    heap.end += size;
+    Some((start, size))
+}
+
+// Unmaps the pages mapped so far if it fails, so that the heap can try growing again.
+fn map_pages(start: usize, size: usize) -> Result<(), HeapError> {
+    let mut mapper = crate::memory::MAPPER
+        .get()
+        .unwrap()
+        .try_lock()
+        .ok_or(HeapError::Contended)?;
+    let mut frame_allocator = crate::memory::FRAME_ALLOCATOR
+        .get()
+        .unwrap()
+        .try_lock()
+        .ok_or(HeapError::Contended)?;
+    let page_range = {
+        let start = Page::containing_address(VirtAddr::new(start as u64));
+        Page::range(start, start + (size as u64 / Size4KiB::SIZE))
+    };
+
+    for page in page_range {
+        let mapped = match frame_allocator.allocate_frame() {
+            Some(frame) => unsafe {
+                mapper.map_to(
+                    page,
+                    frame,
+                    PageTableFlags::PRESENT
+                        | PageTableFlags::WRITABLE
+                        | crate::memory::no_execute(),
+                    frame_allocator.deref_mut(),
+                )
+            }
+            .map_err(|e| {
+                unsafe { frame_allocator.deallocate_frame(frame) };
+                e
+            }),
+            None => Err(MapToError::FrameAllocationFailed),
+        };
+        match mapped {
+            Ok(flush) => flush.flush(),
+            Err(e) => {
+                for mapped in Page::range(page_range.start, page) {
+                    let (frame, flush) = mapper.unmap(mapped).expect("heap page not mapped");
+                    flush.flush();
+                    unsafe { frame_allocator.deallocate_frame(frame) };
+                }
+                return Err(e.into());
+            }
+        }
+    }
+    Ok(())
+}
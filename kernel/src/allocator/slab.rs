@@ -0,0 +1,278 @@
+use crate::memory::{self, FRAME_ALLOCATOR};
+use core::marker::PhantomData;
+use core::mem::{align_of, size_of};
+use core::ops::{Deref, DerefMut};
+use core::ptr::{null_mut, NonNull};
+use spin::Mutex;
+use x86_64::structures::paging::{PageSize, PhysFrame, Size4KiB};
+use x86_64::{PhysAddr, VirtAddr};
+
+// A slab spans enough frames to hold at least this many objects...
+const MIN_OBJECTS: usize = 8;
+// ...unless it would need more frames than this.
+const MAX_SLAB_FRAMES: usize = 16;
+
+// Header at the start of every slab.
+struct Slab {
+    // links the slabs that have both free and used objects
+    next: *mut Slab,
+    prev: *mut Slab,
+    free: *mut FreeObject,
+    in_use: usize,
+}
+
+struct FreeObject {
+    next: *mut FreeObject,
+}
+
+struct Slabs {
+    partial: *mut Slab,
+    stats: SlabStats,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlabStats {
+    pub name: &'static str,
+    /// Size of each object, including padding.
+    pub object_size: usize,
+    pub objects_per_slab: usize,
+    /// Number of slabs currently allocated.
+    pub slabs: usize,
+    pub objects_in_use: usize,
+    /// Total number of allocations since the cache was created.
+    pub allocations: usize,
+}
+
+/// A cache of `T`s allocated from slabs of whole frames.
+///
+/// Allocating is constant time: each slab keeps a list of its free objects. The slab of a freed
+/// object is found from the object's address, and its free list is searched for the object to
+/// catch double frees. A slab is given back to the frame allocator as soon as all of its objects
+/// are free.
+pub struct SlabCache<T> {
+    slabs: Mutex<Slabs>,
+    _marker: PhantomData<T>,
+}
+
+// The raw pointers only ever point into slabs owned by the cache.
+unsafe impl<T: Send> Send for SlabCache<T> {}
+unsafe impl<T: Send> Sync for SlabCache<T> {}
+
+impl<T> SlabCache<T> {
+    pub const fn new(name: &'static str) -> Self {
+        SlabCache {
+            slabs: Mutex::new(Slabs {
+                partial: null_mut(),
+                stats: SlabStats {
+                    name,
+                    object_size: 0,
+                    objects_per_slab: 0,
+                    slabs: 0,
+                    objects_in_use: 0,
+                    allocations: 0,
+                },
+            }),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Moves `value` into the cache.
+    pub fn alloc(&self, value: T) -> Option<SlabBox<T>> {
+        let ptr = self.allocate()?;
+        unsafe { ptr.as_ptr().write(value) };
+        Some(SlabBox { cache: self, ptr })
+    }
+
+    /// Allocates uninitialized memory for a `T`.
+    pub fn allocate(&self) -> Option<NonNull<T>> {
+        let mut slabs = self.slabs.lock();
+        if slabs.partial.is_null() {
+            slabs.partial = Self::new_slab()?;
+            slabs.stats.slabs += 1;
+        }
+        unsafe {
+            let slab = &mut *slabs.partial;
+            let object = slab.free;
+            slab.free = (*object).next;
+            slab.in_use += 1;
+            if slab.free.is_null() {
+                // full slabs aren't tracked, freeing an object will bring them back
+                Self::unlink(&mut slabs, slab);
+            }
+            slabs.stats.objects_in_use += 1;
+            slabs.stats.allocations += 1;
+            Some(NonNull::new_unchecked(object as *mut T))
+        }
+    }
+
+    /// Returns memory obtained from `allocate` to the cache, without dropping its content.
+    pub unsafe fn deallocate(&self, ptr: NonNull<T>) {
+        let mut slabs = self.slabs.lock();
+        let slab = &mut *Self::slab_of(ptr);
+        assert!(slab.in_use > 0, "{:?} is not allocated", ptr);
+
+        let object = ptr.as_ptr() as *mut FreeObject;
+        let mut free = slab.free;
+        while !free.is_null() {
+            assert!(
+                free != object,
+                "double free of {:?} in {}",
+                ptr,
+                slabs.stats.name
+            );
+            free = (*free).next;
+        }
+        if slab.free.is_null() {
+            Self::push(&mut slabs, slab);
+        }
+        (*object).next = slab.free;
+        slab.free = object;
+        slab.in_use -= 1;
+        slabs.stats.objects_in_use -= 1;
+
+        if slab.in_use == 0 {
+            Self::unlink(&mut slabs, slab);
+            Self::free_slab(slab);
+            slabs.stats.slabs -= 1;
+        }
+    }
+
+    pub fn stats(&self) -> SlabStats {
+        let (object_size, _) = Self::object_layout();
+        SlabStats {
+            object_size,
+            objects_per_slab: Self::objects_per_slab(),
+            ..self.slabs.lock().stats
+        }
+    }
+
+    // Size and alignment of each object, large enough to link it while it's free.
+    fn object_layout() -> (usize, usize) {
+        let align = align_of::<T>().max(align_of::<FreeObject>());
+        let size = super::align_up(size_of::<T>().max(size_of::<FreeObject>()), align);
+        (size, align)
+    }
+
+    // Offset of the first object within a slab.
+    fn header_size() -> usize {
+        let (_, align) = Self::object_layout();
+        super::align_up(size_of::<Slab>(), align)
+    }
+
+    fn slab_frames() -> usize {
+        let (size, align) = Self::object_layout();
+        assert!(align as u64 <= Size4KiB::SIZE, "objects are over-aligned");
+        let fit = |frames: usize| (frames * Size4KiB::SIZE as usize - Self::header_size()) / size;
+        let mut frames = 1;
+        while frames < MAX_SLAB_FRAMES && fit(frames) < MIN_OBJECTS {
+            frames *= 2;
+        }
+        assert!(fit(frames) > 0, "objects are too large for a slab");
+        frames
+    }
+
+    fn objects_per_slab() -> usize {
+        let (size, _) = Self::object_layout();
+        (Self::slab_frames() * Size4KiB::SIZE as usize - Self::header_size()) / size
+    }
+
+    fn new_slab() -> Option<*mut Slab> {
+        let frames = FRAME_ALLOCATOR
+            .get()
+            .unwrap()
+            .lock()
+            .allocate_contiguous(Self::slab_frames())?;
+        let start = memory::phys_to_virt(frames.start.start_address());
+        let slab = start.as_mut_ptr::<Slab>();
+
+        // thread every object in the free list
+        let (size, _) = Self::object_layout();
+        let first = start + Self::header_size();
+        let mut free: *mut FreeObject = null_mut();
+        for i in (0..Self::objects_per_slab()).rev() {
+            let object = (first + i * size).as_mut_ptr::<FreeObject>();
+            unsafe { object.write(FreeObject { next: free }) };
+            free = object;
+        }
+        unsafe {
+            slab.write(Slab {
+                next: null_mut(),
+                prev: null_mut(),
+                free,
+                in_use: 0,
+            })
+        };
+        Some(slab)
+    }
+
+    unsafe fn free_slab(slab: *mut Slab) {
+        let start = memory::virt_to_phys(VirtAddr::from_ptr(slab));
+        let first = PhysFrame::containing_address(start);
+        let range = PhysFrame::range(first, first + Self::slab_frames() as u64);
+        FRAME_ALLOCATOR
+            .get()
+            .unwrap()
+            .lock()
+            .deallocate_contiguous(range);
+    }
+
+    // Slabs are allocated from the frame allocator and aligned to their size.
+    fn slab_of(ptr: NonNull<T>) -> *mut Slab {
+        let slab_size = (Self::slab_frames() as u64) * Size4KiB::SIZE;
+        let phys = memory::virt_to_phys(VirtAddr::from_ptr(ptr.as_ptr()));
+        let start = PhysAddr::new(phys.as_u64() & !(slab_size - 1));
+        memory::phys_to_virt(start).as_mut_ptr()
+    }
+
+    unsafe fn push(slabs: &mut Slabs, slab: &mut Slab) {
+        slab.prev = null_mut();
+        slab.next = slabs.partial;
+        if !slabs.partial.is_null() {
+            (*slabs.partial).prev = slab;
+        }
+        slabs.partial = slab;
+    }
+
+    unsafe fn unlink(slabs: &mut Slabs, slab: &mut Slab) {
+        if slab.prev.is_null() {
+            slabs.partial = slab.next;
+        } else {
+            (*slab.prev).next = slab.next;
+        }
+        if !slab.next.is_null() {
+            (*slab.next).prev = slab.prev;
+        }
+        slab.next = null_mut();
+        slab.prev = null_mut();
+    }
+}
+
+/// An object allocated from a `SlabCache`, dropped and returned to the cache when it goes out of
+/// scope.
+pub struct SlabBox<'a, T> {
+    cache: &'a SlabCache<T>,
+    ptr: NonNull<T>,
+}
+
+impl<T> Deref for SlabBox<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T> DerefMut for SlabBox<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T> Drop for SlabBox<'_, T> {
+    fn drop(&mut self) {
+        unsafe {
+            self.ptr.as_ptr().drop_in_place();
+            self.cache.deallocate(self.ptr);
+        }
+    }
+}
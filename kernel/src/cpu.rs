@@ -0,0 +1,114 @@
+use core::arch::x86_64::{__cpuid, __cpuid_count};
+use core::fmt;
+use lazy_static::lazy_static;
+use x86_64::registers::control::{Cr4, Cr4Flags};
+
+/// Processor features the kernel cares about, as reported by CPUID.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Features {
+    /// Supervisor mode execution prevention: the kernel can't execute user pages.
+    pub smep: bool,
+    /// Supervisor mode access prevention: the kernel can't access user pages.
+    pub smap: bool,
+    /// User mode instruction prevention: SGDT, SIDT, SLDT, SMSW and STR fault in user mode.
+    pub umip: bool,
+    /// The NO_EXECUTE page table flag.
+    pub nx: bool,
+    /// The page attribute table, to choose the memory type of mappings.
+    pub pat: bool,
+    /// Process context identifiers, to tag TLB entries.
+    pub pcid: bool,
+    pub rdrand: bool,
+    pub xsave: bool,
+    /// An on-chip local APIC.
+    pub apic: bool,
+    pub x2apic: bool,
+    /// The local APIC timer's TSC-deadline mode.
+    pub tsc_deadline: bool,
+}
+
+impl Features {
+    fn detect() -> Self {
+        let bit = |register: u32, bit: u32| register & (1 << bit) != 0;
+        let mut features = Features::default();
+        unsafe {
+            let max_leaf = __cpuid(0).eax;
+            let max_extended_leaf = __cpuid(0x8000_0000).eax;
+
+            let leaf = __cpuid(1);
+            features.pcid = bit(leaf.ecx, 17);
+            features.x2apic = bit(leaf.ecx, 21);
+            features.tsc_deadline = bit(leaf.ecx, 24);
+            features.xsave = bit(leaf.ecx, 26);
+            features.rdrand = bit(leaf.ecx, 30);
+            features.apic = bit(leaf.edx, 9);
+            features.pat = bit(leaf.edx, 16);
+
+            if max_leaf >= 7 {
+                let leaf = __cpuid_count(7, 0);
+                features.smep = bit(leaf.ebx, 7);
+                features.smap = bit(leaf.ebx, 20);
+                features.umip = bit(leaf.ecx, 2);
+            }
+
+            if max_extended_leaf >= 0x8000_0001 {
+                features.nx = bit(__cpuid(0x8000_0001).edx, 20);
+            }
+        }
+        features
+    }
+
+    fn names(&self) -> [(&'static str, bool); 11] {
+        [
+            ("smep", self.smep),
+            ("smap", self.smap),
+            ("umip", self.umip),
+            ("nx", self.nx),
+            ("pat", self.pat),
+            ("pcid", self.pcid),
+            ("rdrand", self.rdrand),
+            ("xsave", self.xsave),
+            ("apic", self.apic),
+            ("x2apic", self.x2apic),
+            ("tsc-deadline", self.tsc_deadline),
+        ]
+    }
+}
+
+/// Lists the supported features, separated by spaces.
+impl fmt::Display for Features {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut supported = self.names().iter().filter(|(_, s)| *s).map(|(n, _)| *n);
+        if let Some(name) = supported.next() {
+            f.write_str(name)?;
+        }
+        for name in supported {
+            write!(f, " {}", name)?;
+        }
+        Ok(())
+    }
+}
+
+lazy_static! {
+    static ref FEATURES: Features = Features::detect();
+}
+
+pub fn features() -> &'static Features {
+    &FEATURES
+}
+
+/// Enables the supervisor protections that the processor supports.
+pub fn init() {
+    let features = features();
+    let mut flags = Cr4Flags::empty();
+    if features.smep {
+        flags |= Cr4Flags::SUPERVISOR_MODE_EXECUTION_PROTECTION;
+    }
+    if features.smap {
+        flags |= Cr4Flags::SUPERVISOR_MODE_ACCESS_PREVENTION;
+    }
+    if features.umip {
+        flags |= Cr4Flags::USER_MODE_INSTRUCTION_PREVENTION;
+    }
+    unsafe { Cr4::update(|cr4| cr4.insert(flags)) };
+}
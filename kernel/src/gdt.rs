@@ -1,25 +1,18 @@
 use lazy_static::lazy_static;
+use x86_64::instructions::interrupts;
 use x86_64::structures::gdt::{Descriptor, GlobalDescriptorTable, SegmentSelector};
 use x86_64::structures::tss::TaskStateSegment;
 use x86_64::VirtAddr;
 
 pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
 
+// Interrupt stack used until memory is initialized, it has no guard page.
+const BOOT_STACK_SIZE: usize = 4096 * 5;
+static mut DOUBLE_FAULT_BOOT_STACK: [u8; BOOT_STACK_SIZE] = [0; BOOT_STACK_SIZE];
+
 // https://os.phil-opp.com/double-fault-exceptions/#switching-stacks
-lazy_static! {
-    static ref TSS: TaskStateSegment = {
-        let mut tss = TaskStateSegment::new();
-        tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = {
-            const STACK_SIZE: usize = 4096 * 5;
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
-
-            let stack_start = VirtAddr::from_ptr(unsafe { &STACK });
-            let stack_end = stack_start + STACK_SIZE;
-            stack_end
-        };
-        tss
-    };
-}
+// Mutable so that the interrupt stacks can be replaced later, the CPU reads it on every interrupt.
+static mut TSS: TaskStateSegment = TaskStateSegment::new();
 
 struct Selectors {
     kernel_code_segment: SegmentSelector,
@@ -30,7 +23,7 @@ lazy_static! {
     static ref GDT: (GlobalDescriptorTable, Selectors) = {
         let mut gdt = GlobalDescriptorTable::new();
         let kernel_code_segment = gdt.add_entry(Descriptor::kernel_code_segment());
-        let tss_segment = gdt.add_entry(Descriptor::tss_segment(&TSS));
+        let tss_segment = gdt.add_entry(Descriptor::tss_segment(unsafe { &TSS }));
         (
             gdt,
             Selectors {
@@ -42,6 +35,13 @@ lazy_static! {
 }
 
 pub fn init_gdt() {
+    unsafe {
+        set_interrupt_stack(
+            DOUBLE_FAULT_IST_INDEX,
+            VirtAddr::from_ptr(&DOUBLE_FAULT_BOOT_STACK) + BOOT_STACK_SIZE,
+        );
+    }
+
     GDT.0.load();
 
     // https://os.phil-opp.com/double-fault-exceptions/#the-final-steps
@@ -50,3 +50,10 @@ pub fn init_gdt() {
         x86_64::instructions::tables::load_tss(GDT.1.tss_segment);
     }
 }
+
+/// Sets the stack that interrupt handlers with the given stack index switch to.
+///
+/// Unsafe since `top` must be the top of a mapped stack that nothing else uses.
+pub unsafe fn set_interrupt_stack(index: u16, top: VirtAddr) {
+    interrupts::without_interrupts(|| TSS.interrupt_stack_table[index as usize] = top);
+}
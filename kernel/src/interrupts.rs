@@ -0,0 +1,62 @@
+use lazy_static::lazy_static;
+use pic8259_simple::ChainedPics;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+
+pub mod apic;
+pub mod exceptions;
+pub mod irq;
+pub mod page_fault;
+
+pub use irq::{register_irq, unregister_irq, IrqError, IrqHandle, IrqHandler};
+
+lazy_static! {
+    static ref IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        exceptions::set_handlers(&mut idt);
+
+        irq::set_handlers(&mut idt);
+        idt[apic::SPURIOUS_VECTOR as usize].set_handler_fn(spurious_interrupt_handler);
+        idt
+    };
+}
+
+pub fn init_idt() {
+    IDT.load();
+}
+
+fn timer_interrupt_handler() {
+    crate::time::tick();
+}
+
+fn keyboard_interrupt_handler() {
+    // keyboard scancode port
+    let mut port = x86_64::instructions::port::Port::new(0x60);
+    let scancode: u8 = unsafe { port.read() };
+
+    crate::task::keyboard::add_scancode(scancode);
+}
+
+// Not a real interrupt, so it isn't acknowledged.
+extern "x86-interrupt" fn spurious_interrupt_handler(_: &mut InterruptStackFrame) {}
+
+pub const PIC1_OFFSET: u8 = 32;
+pub const PIC2_OFFSET: u8 = PIC1_OFFSET + 8;
+
+pub static PICS: spin::Mutex<ChainedPics> =
+    spin::Mutex::new(unsafe { ChainedPics::new(PIC1_OFFSET, PIC2_OFFSET) });
+
+/// The legacy IRQ lines of the devices that the kernel drives.
+pub const TIMER_IRQ: u8 = 0;
+pub const KEYBOARD_IRQ: u8 = 1;
+
+/// Initializes the PICs with every line masked until a handler is registered for it, and
+/// registers the timer and keyboard handlers.
+pub fn init_pics() {
+    unsafe {
+        PICS.lock().initialize();
+        irq::mask_pics();
+    }
+    register_irq(TIMER_IRQ, timer_interrupt_handler).expect("failed to register the timer");
+    register_irq(KEYBOARD_IRQ, keyboard_interrupt_handler)
+        .expect("failed to register the keyboard");
+}
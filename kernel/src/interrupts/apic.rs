@@ -0,0 +1,296 @@
+//! Local APIC and I/O APIC driver, replacing the 8259 PICs when ACPI describes the APICs.
+//!
+//! Only the bootstrap processor's local APIC is used, in xAPIC mode. IRQ lines below 16 are ISA
+//! IRQs, subject to the MADT's interrupt source overrides, the others are global system
+//! interrupts. Either way, a line is delivered on the same vector as with the PICs.
+
+use super::irq;
+use crate::memory::mmio::MapError;
+use crate::memory::{self, Caching, Mmio};
+use acpi::platform::{Apic, InterruptModel, Polarity, TriggerMode};
+use acpi::{AcpiError, AcpiTables};
+use alloc::vec::Vec;
+use conquer_once::spin::OnceCell;
+use spin::Mutex;
+use x86_64::PhysAddr;
+
+/// Vector of the local APIC's spurious interrupts, which must not be acknowledged.
+pub const SPURIOUS_VECTOR: u8 = 0xff;
+
+// local APIC registers
+const LAPIC_ID: usize = 0x20;
+const LAPIC_TASK_PRIORITY: usize = 0x80;
+const LAPIC_EOI: usize = 0xb0;
+const LAPIC_SPURIOUS: usize = 0xf0;
+const LAPIC_SOFTWARE_ENABLE: u32 = 1 << 8;
+const LAPIC_TIMER: usize = 0x320;
+const LAPIC_TIMER_INITIAL_COUNT: usize = 0x380;
+const LAPIC_TIMER_CURRENT_COUNT: usize = 0x390;
+const LAPIC_TIMER_DIVIDE: usize = 0x3e0;
+const LAPIC_MASKED: u32 = 1 << 16;
+// divides the timer's clock by TIMER_DIVISOR
+const LAPIC_DIVIDE_BY_16: u32 = 0b0011;
+const LAPIC_SIZE: usize = 0x400;
+
+/// What the local APIC timer's clock is divided by.
+pub const TIMER_DIVISOR: u32 = 16;
+
+// I/O APIC registers, accessed indirectly through the select and window registers
+const IOAPIC_SELECT: usize = 0x00;
+const IOAPIC_WINDOW: usize = 0x10;
+const IOAPIC_VERSION: u32 = 0x01;
+const IOAPIC_REDIRECTION: u32 = 0x10;
+const IOAPIC_SIZE: usize = 0x20;
+
+// redirection entry bits
+const ACTIVE_LOW: u64 = 1 << 13;
+const LEVEL_TRIGGERED: u64 = 1 << 15;
+const MASKED: u64 = 1 << 16;
+
+const ISA_IRQS: usize = 16;
+
+#[derive(Debug)]
+pub enum ApicError {
+    Acpi(AcpiError),
+    /// The MADT doesn't describe APICs.
+    NoApic,
+    /// No I/O APIC handles this global system interrupt.
+    NoIoApic(u32),
+    Map(MapError),
+}
+
+impl From<AcpiError> for ApicError {
+    fn from(error: AcpiError) -> Self {
+        ApicError::Acpi(error)
+    }
+}
+
+impl From<MapError> for ApicError {
+    fn from(error: MapError) -> Self {
+        ApicError::Map(error)
+    }
+}
+
+pub struct LocalApic {
+    mmio: Mmio,
+}
+
+impl LocalApic {
+    pub fn id(&self) -> u8 {
+        (unsafe { self.mmio.read::<u32>(LAPIC_ID) } >> 24) as u8
+    }
+
+    pub fn end_of_interrupt(&self) {
+        unsafe { self.mmio.write::<u32>(LAPIC_EOI, 0) }
+    }
+
+    /// Starts the timer counting down from its maximum, without interrupts.
+    pub unsafe fn start_timer_count(&self) {
+        self.mmio.write::<u32>(LAPIC_TIMER, LAPIC_MASKED);
+        self.mmio
+            .write::<u32>(LAPIC_TIMER_DIVIDE, LAPIC_DIVIDE_BY_16);
+        self.mmio.write::<u32>(LAPIC_TIMER_INITIAL_COUNT, u32::MAX);
+    }
+
+    /// Number of times the timer was decremented since `start_timer_count`, once every
+    /// `TIMER_DIVISOR` cycles of its clock.
+    pub fn timer_count(&self) -> u32 {
+        u32::MAX - unsafe { self.mmio.read::<u32>(LAPIC_TIMER_CURRENT_COUNT) }
+    }
+
+    pub unsafe fn stop_timer(&self) {
+        self.mmio.write::<u32>(LAPIC_TIMER_INITIAL_COUNT, 0);
+    }
+
+    unsafe fn enable(&self) {
+        // accept every interrupt
+        self.mmio.write::<u32>(LAPIC_TASK_PRIORITY, 0);
+        self.mmio.write::<u32>(
+            LAPIC_SPURIOUS,
+            LAPIC_SOFTWARE_ENABLE | SPURIOUS_VECTOR as u32,
+        );
+    }
+}
+
+pub struct IoApic {
+    mmio: Mmio,
+    id: u8,
+    gsi_base: u32,
+    entries: u32,
+}
+
+impl IoApic {
+    unsafe fn new(id: u8, address: u32, gsi_base: u32) -> Result<Self, MapError> {
+        let mmio = memory::map_mmio(
+            PhysAddr::new(address as u64),
+            IOAPIC_SIZE,
+            Caching::Uncached,
+        )?;
+        let mut io_apic = IoApic {
+            mmio,
+            id,
+            gsi_base,
+            entries: 0,
+        };
+        io_apic.entries = ((io_apic.read(IOAPIC_VERSION) >> 16) & 0xff) + 1;
+        Ok(io_apic)
+    }
+
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// Whether this I/O APIC handles the global system interrupt `gsi`.
+    pub fn handles(&self, gsi: u32) -> bool {
+        self.gsi_base <= gsi && gsi < self.gsi_base + self.entries
+    }
+
+    unsafe fn read(&self, register: u32) -> u32 {
+        self.mmio.write(IOAPIC_SELECT, register);
+        self.mmio.read(IOAPIC_WINDOW)
+    }
+
+    unsafe fn write(&self, register: u32, value: u32) {
+        self.mmio.write(IOAPIC_SELECT, register);
+        self.mmio.write(IOAPIC_WINDOW, value);
+    }
+
+    unsafe fn set_redirection(&self, gsi: u32, entry: u64) {
+        let register = IOAPIC_REDIRECTION + (gsi - self.gsi_base) * 2;
+        // the high half holds the destination, it's written first so that the entry is never
+        // unmasked with the wrong one
+        self.write(register + 1, (entry >> 32) as u32);
+        self.write(register, entry as u32);
+    }
+
+    unsafe fn mask_all(&self) {
+        for gsi in self.gsi_base..self.gsi_base + self.entries {
+            self.set_redirection(gsi, MASKED);
+        }
+    }
+}
+
+static LOCAL_APIC: OnceCell<LocalApic> = OnceCell::uninit();
+// Only locked with interrupts disabled.
+static IO_APICS: Mutex<Vec<IoApic>> = Mutex::new(Vec::new());
+// The global system interrupt and polarity and trigger mode bits of every ISA IRQ.
+static ISA_ROUTES: Mutex<[(u32, u64); ISA_IRQS]> = Mutex::new([(0, 0); ISA_IRQS]);
+
+/// The bootstrap processor's local APIC, once the APICs are in use.
+pub fn local_apic() -> Option<&'static LocalApic> {
+    LOCAL_APIC.get()
+}
+
+// The global system interrupt of an ISA IRQ, and its redirection entry bits: it's edge triggered
+// and active high unless overridden.
+fn isa_route(apic: &Apic, irq: u8) -> (u32, u64) {
+    let mut entry = 0;
+    let gsi = match apic
+        .interrupt_source_overrides
+        .iter()
+        .find(|o| o.isa_source == irq)
+    {
+        Some(o) => {
+            if let Polarity::ActiveLow = o.polarity {
+                entry |= ACTIVE_LOW;
+            }
+            if let TriggerMode::Level = o.trigger_mode {
+                entry |= LEVEL_TRIGGERED;
+            }
+            o.global_system_interrupt
+        }
+        None => irq as u32,
+    };
+    (gsi, entry)
+}
+
+// The global system interrupt of a line and the bits of its redirection entry.
+fn route_of(irq: u8, isa_routes: &[(u32, u64); ISA_IRQS]) -> (u32, u64) {
+    match isa_routes.get(irq as usize) {
+        Some(&route) => route,
+        // PCI interrupts are level triggered and active low
+        None => (irq as u32, LEVEL_TRIGGERED | ACTIVE_LOW),
+    }
+}
+
+/// Delivers `irq` to the bootstrap processor on `vector`, interrupts must be disabled.
+pub(super) unsafe fn route(irq: u8, vector: u8) -> Result<(), ApicError> {
+    let (gsi, bits) = route_of(irq, &ISA_ROUTES.lock());
+    let destination = local_apic().expect("APIC not initialized").id();
+    let io_apics = IO_APICS.lock();
+    let io_apic = io_apics
+        .iter()
+        .find(|io_apic| io_apic.handles(gsi))
+        .ok_or(ApicError::NoIoApic(gsi))?;
+    io_apic.set_redirection(gsi, vector as u64 | bits | (destination as u64) << 56);
+    Ok(())
+}
+
+/// Stops delivering `irq`, interrupts must be disabled.
+pub(super) unsafe fn mask(irq: u8) {
+    let (gsi, _) = route_of(irq, &ISA_ROUTES.lock());
+    if let Some(io_apic) = IO_APICS.lock().iter().find(|io_apic| io_apic.handles(gsi)) {
+        io_apic.set_redirection(gsi, MASKED);
+    }
+}
+
+/// Switches from the PICs to the APICs described by the MADT, the lines that have handlers being
+/// routed through the I/O APICs.
+///
+/// The PICs are left in charge if this fails, so the caller can carry on.
+pub unsafe fn init(tables: &AcpiTables<crate::acpi::Handler>) -> Result<(), ApicError> {
+    let apic = match tables.platform_info()?.interrupt_model {
+        InterruptModel::Apic(apic) => apic,
+        _ => return Err(ApicError::NoApic),
+    };
+
+    let local_apic = LocalApic {
+        mmio: memory::map_mmio(
+            PhysAddr::new(apic.local_apic_address),
+            LAPIC_SIZE,
+            Caching::Uncached,
+        )?,
+    };
+    let mut io_apics = Vec::new();
+    for io_apic in apic.io_apics.iter() {
+        io_apics.push(IoApic::new(
+            io_apic.id,
+            io_apic.address,
+            io_apic.global_system_interrupt_base,
+        )?);
+    }
+    let mut isa_routes = [(0, 0); ISA_IRQS];
+    for (irq, route) in isa_routes.iter_mut().enumerate() {
+        *route = isa_route(&apic, irq as u8);
+    }
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        // every enabled line must keep working
+        let mut unroutable = None;
+        irq::for_each_enabled_line(|irq| {
+            let (gsi, _) = route_of(irq, &isa_routes);
+            if !io_apics.iter().any(|io_apic| io_apic.handles(gsi)) {
+                unroutable = Some(gsi);
+            }
+        });
+        if let Some(gsi) = unroutable {
+            return Err(ApicError::NoIoApic(gsi));
+        }
+
+        irq::mask_pics();
+        for io_apic in io_apics.iter() {
+            io_apic.mask_all();
+        }
+        local_apic.enable();
+        LOCAL_APIC.init_once(|| local_apic);
+        *IO_APICS.lock() = io_apics;
+        *ISA_ROUTES.lock() = isa_routes;
+        let mut routed = Ok(());
+        irq::for_each_enabled_line(|irq| {
+            if routed.is_ok() {
+                routed = route(irq, irq::vector(irq));
+            }
+        });
+        routed
+    })
+}
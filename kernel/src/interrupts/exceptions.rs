@@ -0,0 +1,490 @@
+//! Handlers for the architectural exceptions, which report what happened and panic.
+//!
+//! The report names the exception, decodes its error code, and shows the general purpose and
+//! control registers and the interrupt stack frame. Every exception enters through a stub that
+//! saves the general purpose registers before any Rust code runs, so they're reported as they were
+//! when the exception happened.
+
+use super::page_fault::PageFault;
+use crate::println;
+use core::fmt;
+use x86_64::registers::control::{Cr0, Cr0Flags, Cr2, Cr3, Cr3Flags, Cr4, Cr4Flags};
+use x86_64::registers::model_specific::{Efer, EferFlags};
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+use x86_64::structures::paging::PhysFrame;
+use x86_64::VirtAddr;
+
+#[derive(Debug)]
+pub struct Exception {
+    pub vector: u8,
+    pub mnemonic: &'static str,
+    pub name: &'static str,
+}
+
+macro_rules! exceptions {
+    ($($constant:ident = $vector:literal $mnemonic:literal $name:literal,)*) => {
+        $(
+            pub const $constant: Exception = Exception {
+                vector: $vector,
+                mnemonic: $mnemonic,
+                name: $name,
+            };
+        )*
+    };
+}
+
+exceptions! {
+    DIVIDE_ERROR = 0 "#DE" "Divide error",
+    DEBUG = 1 "#DB" "Debug",
+    NON_MASKABLE_INTERRUPT = 2 "NMI" "Non-maskable interrupt",
+    BREAKPOINT = 3 "#BP" "Breakpoint",
+    OVERFLOW = 4 "#OF" "Overflow",
+    BOUND_RANGE_EXCEEDED = 5 "#BR" "Bound range exceeded",
+    INVALID_OPCODE = 6 "#UD" "Invalid opcode",
+    DEVICE_NOT_AVAILABLE = 7 "#NM" "Device not available",
+    DOUBLE_FAULT = 8 "#DF" "Double fault",
+    INVALID_TSS = 10 "#TS" "Invalid TSS",
+    SEGMENT_NOT_PRESENT = 11 "#NP" "Segment not present",
+    STACK_SEGMENT_FAULT = 12 "#SS" "Stack-segment fault",
+    GENERAL_PROTECTION_FAULT = 13 "#GP" "General protection fault",
+    PAGE_FAULT = 14 "#PF" "Page fault",
+    X87_FLOATING_POINT = 16 "#MF" "x87 floating-point exception",
+    ALIGNMENT_CHECK = 17 "#AC" "Alignment check",
+    MACHINE_CHECK = 18 "#MC" "Machine check",
+    SIMD_FLOATING_POINT = 19 "#XM" "SIMD floating-point exception",
+    VIRTUALIZATION = 20 "#VE" "Virtualization exception",
+    SECURITY_EXCEPTION = 30 "#SX" "Security exception",
+}
+
+/// The error code of the exceptions caused by a segment selector or an IDT entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SelectorError(pub u64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DescriptorTable {
+    Gdt,
+    Idt,
+    Ldt,
+}
+
+impl SelectorError {
+    /// Whether an event external to the program, such as an interrupt, caused the exception.
+    pub fn is_external(&self) -> bool {
+        self.0 & 1 != 0
+    }
+
+    pub fn table(&self) -> DescriptorTable {
+        match (self.0 >> 1) & 0b11 {
+            0b00 => DescriptorTable::Gdt,
+            0b10 => DescriptorTable::Ldt,
+            _ => DescriptorTable::Idt,
+        }
+    }
+
+    /// Index of the descriptor in its table, the vector for the IDT.
+    pub fn index(&self) -> u16 {
+        ((self.0 >> 3) & 0x1fff) as u16
+    }
+}
+
+impl fmt::Display for SelectorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0 == 0 {
+            return f.write_str("not caused by a selector");
+        }
+        match self.table() {
+            DescriptorTable::Gdt => write!(f, "GDT index {}", self.index())?,
+            DescriptorTable::Ldt => write!(f, "LDT index {}", self.index())?,
+            DescriptorTable::Idt => write!(f, "IDT vector {}", self.index())?,
+        }
+        if self.is_external() {
+            f.write_str(", external event")?;
+        }
+        Ok(())
+    }
+}
+
+/// The error code pushed by an exception, decoded when it has a meaning.
+#[derive(Debug, Clone, Copy)]
+pub enum ErrorCode {
+    None,
+    Raw(u64),
+    Selector(SelectorError),
+    Page(PageFault),
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorCode::None => f.write_str("none"),
+            ErrorCode::Raw(code) => write!(f, "{:#x}", code),
+            ErrorCode::Selector(error) => write!(f, "{:#x}: {}", error.0, error),
+            ErrorCode::Page(fault) => write!(f, "{}", fault),
+        }
+    }
+}
+
+/// The general purpose registers when the exception happened, in the order the entry stubs push
+/// them.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct GeneralRegisters {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+}
+
+impl fmt::Display for GeneralRegisters {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let registers = [
+            ("RAX:", self.rax),
+            ("RBX:", self.rbx),
+            ("RCX:", self.rcx),
+            ("RDX:", self.rdx),
+            ("RSI:", self.rsi),
+            ("RDI:", self.rdi),
+            ("RBP:", self.rbp),
+            ("R8: ", self.r8),
+            ("R9: ", self.r9),
+            ("R10:", self.r10),
+            ("R11:", self.r11),
+            ("R12:", self.r12),
+            ("R13:", self.r13),
+            ("R14:", self.r14),
+            ("R15:", self.r15),
+        ];
+        for (i, (name, value)) in registers.iter().enumerate() {
+            // three per line
+            let separator = match i % 3 {
+                2 if i + 1 < registers.len() => "\n",
+                2 => "",
+                _ => " ",
+            };
+            write!(f, "{} {:#018x}{}", name, value, separator)?;
+        }
+        Ok(())
+    }
+}
+
+/// What an entry stub leaves on the stack for the handler: the saved registers, the error code,
+/// zero for the exceptions without one, and the frame pushed by the CPU.
+#[repr(C)]
+pub struct ExceptionStack {
+    pub registers: GeneralRegisters,
+    pub error_code: u64,
+    pub frame: InterruptStackFrame,
+}
+
+/// The control registers when the exception was handled.
+#[derive(Debug, Clone, Copy)]
+pub struct Registers {
+    pub cr0: Cr0Flags,
+    pub cr2: VirtAddr,
+    pub cr3: (PhysFrame, Cr3Flags),
+    pub cr4: Cr4Flags,
+    pub efer: EferFlags,
+}
+
+impl Registers {
+    pub fn read() -> Self {
+        Registers {
+            cr0: Cr0::read(),
+            cr2: Cr2::read(),
+            cr3: Cr3::read(),
+            cr4: Cr4::read(),
+            efer: unsafe { Efer::read() },
+        }
+    }
+}
+
+impl fmt::Display for Registers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "CR0:  {:?}", self.cr0)?;
+        writeln!(f, "CR2:  {:#x}", self.cr2.as_u64())?;
+        writeln!(
+            f,
+            "CR3:  {:#x} {:?}",
+            self.cr3.0.start_address().as_u64(),
+            self.cr3.1
+        )?;
+        writeln!(f, "CR4:  {:?}", self.cr4)?;
+        write!(f, "EFER: {:?}", self.efer)
+    }
+}
+
+/// Everything known about an exception, `Display`ed the same way for all of them.
+pub struct CrashReport<'a> {
+    pub exception: &'static Exception,
+    pub error_code: ErrorCode,
+    pub general_registers: GeneralRegisters,
+    pub registers: Registers,
+    pub frame: &'a InterruptStackFrame,
+}
+
+impl<'a> CrashReport<'a> {
+    pub fn new(
+        exception: &'static Exception,
+        error_code: ErrorCode,
+        stack: &'a ExceptionStack,
+    ) -> Self {
+        CrashReport {
+            exception,
+            error_code,
+            general_registers: stack.registers,
+            registers: Registers::read(),
+            frame: &stack.frame,
+        }
+    }
+}
+
+impl fmt::Display for CrashReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let exception = self.exception;
+        writeln!(
+            f,
+            "EXCEPTION: {} ({}, vector {})",
+            exception.name, exception.mnemonic, exception.vector
+        )?;
+        writeln!(f, "error code: {}", self.error_code)?;
+        writeln!(f, "{}", self.general_registers)?;
+        writeln!(f, "{}", self.registers)?;
+        write!(f, "{:#?}", self.frame)
+    }
+}
+
+/// Panics with the report of an exception that can't be recovered from.
+pub fn crash(exception: &'static Exception, error_code: ErrorCode, stack: &ExceptionStack) -> ! {
+    panic!("{}", CrashReport::new(exception, error_code, stack))
+}
+
+fn selector(code: u64) -> ErrorCode {
+    ErrorCode::Selector(SelectorError(code))
+}
+
+// Defines the entry stub `$stub` of an exception, which calls `$handler` with the
+// `ExceptionStack` it builds: it pushes a zero error code for the exceptions without one, then
+// the general purpose registers. The registers are restored if the handler returns.
+//
+// The CPU aligns the stack on 16 bytes before pushing the frame, so it's 8 bytes off once the
+// registers are pushed.
+macro_rules! entry_stub {
+    ($stub:ident, $handler:ident) => {
+        entry_stub!(@stub $stub, $handler, "push 0\n");
+    };
+    ($stub:ident, $handler:ident, error_code) => {
+        entry_stub!(@stub $stub, $handler, "");
+    };
+    (@stub $stub:ident, $handler:ident, $push_error_code:expr) => {
+        global_asm!(concat!(
+            ".intel_syntax noprefix\n",
+            ".global ", stringify!($stub), "\n",
+            stringify!($stub), ":\n",
+            $push_error_code,
+            "push rax\n",
+            "push rbx\n",
+            "push rcx\n",
+            "push rdx\n",
+            "push rsi\n",
+            "push rdi\n",
+            "push rbp\n",
+            "push r8\n",
+            "push r9\n",
+            "push r10\n",
+            "push r11\n",
+            "push r12\n",
+            "push r13\n",
+            "push r14\n",
+            "push r15\n",
+            "mov rdi, rsp\n",
+            "cld\n",
+            "sub rsp, 8\n",
+            "call ", stringify!($handler), "\n",
+            "add rsp, 8\n",
+            "pop r15\n",
+            "pop r14\n",
+            "pop r13\n",
+            "pop r12\n",
+            "pop r11\n",
+            "pop r10\n",
+            "pop r9\n",
+            "pop r8\n",
+            "pop rbp\n",
+            "pop rdi\n",
+            "pop rsi\n",
+            "pop rdx\n",
+            "pop rcx\n",
+            "pop rbx\n",
+            "pop rax\n",
+            // the error code
+            "add rsp, 8\n",
+            "iretq\n",
+            ".att_syntax\n",
+        ));
+
+        extern "C" {
+            fn $stub();
+        }
+    };
+}
+
+// Defines the entry stub and the handler of an exception that crashes, decoding the error code
+// with `$decode` if there's one.
+macro_rules! crash_handler {
+    ($stub:ident, $handler:ident, $exception:ident) => {
+        entry_stub!($stub, $handler);
+
+        #[no_mangle]
+        extern "C" fn $handler(stack: &mut ExceptionStack) -> ! {
+            crash(&$exception, ErrorCode::None, stack)
+        }
+    };
+    ($stub:ident, $handler:ident, $exception:ident, $decode:expr) => {
+        entry_stub!($stub, $handler, error_code);
+
+        #[no_mangle]
+        extern "C" fn $handler(stack: &mut ExceptionStack) -> ! {
+            crash(&$exception, $decode(stack.error_code), stack)
+        }
+    };
+}
+
+crash_handler!(divide_error_entry, divide_error_handler, DIVIDE_ERROR);
+crash_handler!(debug_entry, debug_handler, DEBUG);
+crash_handler!(
+    non_maskable_interrupt_entry,
+    non_maskable_interrupt_handler,
+    NON_MASKABLE_INTERRUPT
+);
+crash_handler!(overflow_entry, overflow_handler, OVERFLOW);
+crash_handler!(
+    bound_range_exceeded_entry,
+    bound_range_exceeded_handler,
+    BOUND_RANGE_EXCEEDED
+);
+crash_handler!(invalid_opcode_entry, invalid_opcode_handler, INVALID_OPCODE);
+crash_handler!(
+    device_not_available_entry,
+    device_not_available_handler,
+    DEVICE_NOT_AVAILABLE
+);
+crash_handler!(
+    invalid_tss_entry,
+    invalid_tss_handler,
+    INVALID_TSS,
+    selector
+);
+crash_handler!(
+    segment_not_present_entry,
+    segment_not_present_handler,
+    SEGMENT_NOT_PRESENT,
+    selector
+);
+crash_handler!(
+    stack_segment_fault_entry,
+    stack_segment_fault_handler,
+    STACK_SEGMENT_FAULT,
+    selector
+);
+crash_handler!(
+    general_protection_fault_entry,
+    general_protection_fault_handler,
+    GENERAL_PROTECTION_FAULT,
+    selector
+);
+crash_handler!(
+    x87_floating_point_entry,
+    x87_floating_point_handler,
+    X87_FLOATING_POINT
+);
+crash_handler!(
+    alignment_check_entry,
+    alignment_check_handler,
+    ALIGNMENT_CHECK,
+    ErrorCode::Raw
+);
+crash_handler!(machine_check_entry, machine_check_handler, MACHINE_CHECK);
+crash_handler!(
+    simd_floating_point_entry,
+    simd_floating_point_handler,
+    SIMD_FLOATING_POINT
+);
+crash_handler!(virtualization_entry, virtualization_handler, VIRTUALIZATION);
+crash_handler!(
+    security_exception_entry,
+    security_exception_handler,
+    SECURITY_EXCEPTION,
+    ErrorCode::Raw
+);
+
+// The only exception that execution continues after.
+entry_stub!(breakpoint_entry, breakpoint_handler);
+
+#[no_mangle]
+extern "C" fn breakpoint_handler(stack: &mut ExceptionStack) {
+    println!("{}", CrashReport::new(&BREAKPOINT, ErrorCode::None, stack));
+}
+
+// The page fault of a stack overflow can't be pushed on the overflowed stack, so it double faults.
+entry_stub!(double_fault_entry, double_fault_handler, error_code);
+
+#[no_mangle]
+extern "C" fn double_fault_handler(stack: &mut ExceptionStack) -> ! {
+    let error_code = ErrorCode::Raw(stack.error_code);
+    if let Some(overflowed) = crate::memory::stack::overflowed(Cr2::read()) {
+        panic!(
+            "stack overflow in {}\n{}",
+            overflowed.name(),
+            CrashReport::new(&DOUBLE_FAULT, error_code, stack)
+        );
+    }
+    crash(&DOUBLE_FAULT, error_code, stack)
+}
+
+// Its handler is in `page_fault`.
+entry_stub!(page_fault_entry, page_fault_handler, error_code);
+
+// The IDT only takes handlers of the x86-interrupt ABI, the stubs are installed by address.
+macro_rules! set_stub {
+    ($entry:expr, $stub:ident) => {
+        $entry.set_handler_fn(unsafe { core::mem::transmute($stub as unsafe extern "C" fn()) })
+    };
+}
+
+/// Installs the entry stubs of every exception.
+pub(super) fn set_handlers(idt: &mut InterruptDescriptorTable) {
+    set_stub!(idt.divide_error, divide_error_entry);
+    set_stub!(idt.debug, debug_entry);
+    set_stub!(idt.non_maskable_interrupt, non_maskable_interrupt_entry);
+    set_stub!(idt.breakpoint, breakpoint_entry);
+    set_stub!(idt.overflow, overflow_entry);
+    set_stub!(idt.bound_range_exceeded, bound_range_exceeded_entry);
+    set_stub!(idt.invalid_opcode, invalid_opcode_entry);
+    set_stub!(idt.device_not_available, device_not_available_entry);
+    let double_fault = set_stub!(idt.double_fault, double_fault_entry);
+    unsafe { double_fault.set_stack_index(crate::gdt::DOUBLE_FAULT_IST_INDEX) };
+    set_stub!(idt.invalid_tss, invalid_tss_entry);
+    set_stub!(idt.segment_not_present, segment_not_present_entry);
+    set_stub!(idt.stack_segment_fault, stack_segment_fault_entry);
+    set_stub!(idt.general_protection_fault, general_protection_fault_entry);
+    // On the faulting stack, a nested page fault would overwrite the outer one's frame on an
+    // interrupt stack. Overflowing the current stack double faults instead.
+    set_stub!(idt.page_fault, page_fault_entry);
+    set_stub!(idt.x87_floating_point, x87_floating_point_entry);
+    set_stub!(idt.alignment_check, alignment_check_entry);
+    set_stub!(idt.machine_check, machine_check_entry);
+    set_stub!(idt.simd_floating_point, simd_floating_point_entry);
+    set_stub!(idt.virtualization, virtualization_entry);
+    set_stub!(idt.security_exception, security_exception_entry);
+}
@@ -0,0 +1,213 @@
+//! IRQ lines that drivers register handlers for.
+//!
+//! Every vector after the exceptions goes through a stub that dispatches to the handlers
+//! registered for its line, and then acknowledges the interrupt to the active controller.
+
+use super::{apic, PIC1_OFFSET, PICS};
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::instructions::port::Port;
+use x86_64::structures::idt::{HandlerFunc, InterruptDescriptorTable, InterruptStackFrame};
+
+/// Vector of IRQ line 0, the other lines use the vectors that follow.
+pub const IRQ_BASE: u8 = PIC1_OFFSET;
+/// Number of IRQ lines: every vector after `IRQ_BASE` but the APIC's spurious vector.
+pub const IRQ_LINES: usize = (apic::SPURIOUS_VECTOR - IRQ_BASE) as usize;
+/// Number of handlers that can share a line.
+pub const MAX_SHARED: usize = 4;
+
+const PIC_LINES: u8 = 16;
+// the secondary PIC is cascaded into this line of the primary
+const PIC_CASCADE: u8 = 2;
+
+/// Called on every interrupt of a line, interrupts being disabled.
+pub type IrqHandler = fn();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqError {
+    /// The line is past `IRQ_LINES`.
+    InvalidIrq,
+    /// `MAX_SHARED` handlers are registered on the line.
+    TooManyHandlers,
+    /// The active interrupt controller can't deliver the line.
+    Unroutable,
+}
+
+/// A registered handler, to unregister it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IrqHandle {
+    irq: u8,
+    slot: usize,
+}
+
+impl IrqHandle {
+    pub fn irq(&self) -> u8 {
+        self.irq
+    }
+}
+
+// Only locked with interrupts disabled, so that it's never held when an interrupt is dispatched.
+static HANDLERS: Mutex<[[Option<IrqHandler>; MAX_SHARED]; IRQ_LINES]> =
+    Mutex::new([[None; MAX_SHARED]; IRQ_LINES]);
+
+/// The vector that `irq` is delivered on.
+pub fn vector(irq: u8) -> u8 {
+    IRQ_BASE + irq
+}
+
+/// Adds `handler` to the handlers of `irq`, enabling the line if it's the first one.
+pub fn register_irq(irq: u8, handler: IrqHandler) -> Result<IrqHandle, IrqError> {
+    if irq as usize >= IRQ_LINES {
+        return Err(IrqError::InvalidIrq);
+    }
+    without_interrupts(|| {
+        let mut handlers = HANDLERS.lock();
+        let line = &mut handlers[irq as usize];
+        let slot = line
+            .iter()
+            .position(Option::is_none)
+            .ok_or(IrqError::TooManyHandlers)?;
+        if line.iter().all(Option::is_none) {
+            enable_line(irq)?;
+        }
+        line[slot] = Some(handler);
+        Ok(IrqHandle { irq, slot })
+    })
+}
+
+/// Removes a handler, disabling the line if it was the last one.
+pub fn unregister_irq(handle: IrqHandle) {
+    without_interrupts(|| {
+        let mut handlers = HANDLERS.lock();
+        let line = &mut handlers[handle.irq as usize];
+        assert!(
+            line[handle.slot].take().is_some(),
+            "IRQ handler already unregistered"
+        );
+        if line.iter().all(Option::is_none) {
+            disable_line(handle.irq);
+        }
+    })
+}
+
+/// Number of handlers registered on `irq`, 0 past `IRQ_LINES`.
+pub fn handlers(irq: u8) -> usize {
+    if irq as usize >= IRQ_LINES {
+        return 0;
+    }
+    without_interrupts(|| HANDLERS.lock()[irq as usize].iter().flatten().count())
+}
+
+// Calls `f` with every line that has handlers, interrupts must be disabled.
+pub(super) fn for_each_enabled_line(mut f: impl FnMut(u8)) {
+    let handlers = HANDLERS.lock();
+    for (irq, line) in handlers.iter().enumerate() {
+        if line.iter().any(Option::is_some) {
+            f(irq as u8);
+        }
+    }
+}
+
+fn enable_line(irq: u8) -> Result<(), IrqError> {
+    if apic::local_apic().is_some() {
+        unsafe { apic::route(irq, vector(irq)) }.map_err(|_| IrqError::Unroutable)
+    } else if irq < PIC_LINES {
+        unsafe { set_pic_masked(irq, false) };
+        Ok(())
+    } else {
+        Err(IrqError::Unroutable)
+    }
+}
+
+fn disable_line(irq: u8) {
+    if apic::local_apic().is_some() {
+        unsafe { apic::mask(irq) };
+    } else if irq < PIC_LINES {
+        unsafe { set_pic_masked(irq, true) };
+    }
+}
+
+unsafe fn set_pic_masked(irq: u8, masked: bool) {
+    let (mut port, bit) = if irq < 8 {
+        (Port::<u8>::new(0x21), irq)
+    } else {
+        (Port::<u8>::new(0xa1), irq - 8)
+    };
+    let mask = port.read();
+    port.write(if masked {
+        mask | 1 << bit
+    } else {
+        mask & !(1 << bit)
+    });
+    if irq >= 8 && !masked {
+        set_pic_masked(PIC_CASCADE, false);
+    }
+}
+
+/// Masks every line of both PICs.
+pub(super) unsafe fn mask_pics() {
+    Port::<u8>::new(0xa1).write(0xff);
+    Port::<u8>::new(0x21).write(0xff);
+}
+
+fn end_of_interrupt(vector: u8) {
+    match apic::local_apic() {
+        Some(local_apic) => local_apic.end_of_interrupt(),
+        None => unsafe { PICS.lock().notify_end_of_interrupt(vector) },
+    }
+}
+
+fn dispatch(vector: u8) {
+    // copied so that handlers can register other handlers
+    let line = HANDLERS.lock()[(vector - IRQ_BASE) as usize];
+    for handler in line.iter().flatten() {
+        handler();
+    }
+    end_of_interrupt(vector);
+}
+
+macro_rules! stub {
+    ($vector:expr) => {{
+        extern "x86-interrupt" fn stub(_: &mut InterruptStackFrame) {
+            dispatch($vector);
+        }
+        stub as HandlerFunc
+    }};
+}
+
+// A row of 16 stubs, for the vectors starting at `$high * 16`.
+macro_rules! stubs {
+    ($($high:literal)*) => {
+        [$(stubs!(@row $high)),*]
+    };
+    (@row $high:literal) => {
+        [
+            stub!($high * 16),
+            stub!($high * 16 + 1),
+            stub!($high * 16 + 2),
+            stub!($high * 16 + 3),
+            stub!($high * 16 + 4),
+            stub!($high * 16 + 5),
+            stub!($high * 16 + 6),
+            stub!($high * 16 + 7),
+            stub!($high * 16 + 8),
+            stub!($high * 16 + 9),
+            stub!($high * 16 + 10),
+            stub!($high * 16 + 11),
+            stub!($high * 16 + 12),
+            stub!($high * 16 + 13),
+            stub!($high * 16 + 14),
+            stub!($high * 16 + 15),
+        ]
+    };
+}
+
+// From vector 0x20 to 0xff.
+static STUBS: [[HandlerFunc; 16]; 14] = stubs!(2 3 4 5 6 7 8 9 10 11 12 13 14 15);
+
+pub(super) fn set_handlers(idt: &mut InterruptDescriptorTable) {
+    let stubs = STUBS.iter().flatten().take(IRQ_LINES);
+    for (vector, &stub) in (IRQ_BASE as usize..).zip(stubs) {
+        idt[vector].set_handler_fn(stub);
+    }
+}
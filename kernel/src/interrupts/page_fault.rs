@@ -0,0 +1,198 @@
+use super::exceptions::{self, ErrorCode, ExceptionStack};
+use core::fmt;
+use core::mem::size_of;
+use spin::Mutex;
+use x86_64::registers::control::Cr2;
+use x86_64::structures::idt::PageFaultErrorCode;
+use x86_64::VirtAddr;
+
+const MAX_RESOLVERS: usize = 8;
+
+/// A page fault, as seen by resolvers.
+#[derive(Debug, Clone, Copy)]
+pub struct PageFault {
+    /// The address that was accessed.
+    pub addr: VirtAddr,
+    pub error_code: PageFaultErrorCode,
+    /// Address of the faulting instruction.
+    pub ip: VirtAddr,
+}
+
+impl PageFault {
+    /// Whether the page was present, meaning the access wasn't allowed.
+    pub fn is_protection_violation(&self) -> bool {
+        self.error_code
+            .contains(PageFaultErrorCode::PROTECTION_VIOLATION)
+    }
+
+    pub fn is_write(&self) -> bool {
+        self.error_code
+            .contains(PageFaultErrorCode::CAUSED_BY_WRITE)
+    }
+}
+
+impl fmt::Display for PageFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let code = self.error_code;
+        let access = if code.contains(PageFaultErrorCode::INSTRUCTION_FETCH) {
+            "instruction fetch from"
+        } else if self.is_write() {
+            "write to"
+        } else {
+            "read from"
+        };
+        let cause = if code.contains(PageFaultErrorCode::MALFORMED_TABLE) {
+            "reserved bit set in a page table"
+        } else if self.is_protection_violation() {
+            "protection violation"
+        } else {
+            "page not present"
+        };
+        let mode = if code.contains(PageFaultErrorCode::USER_MODE) {
+            "user"
+        } else {
+            "kernel"
+        };
+        write!(
+            f,
+            "{} {:#x} at ip {:#x}: {} in {} mode ({:?})",
+            access,
+            self.addr.as_u64(),
+            self.ip.as_u64(),
+            cause,
+            mode,
+            code
+        )
+    }
+}
+
+/// Handles a page fault if it's one it knows about, returns whether it did.
+///
+/// Resolvers run in the page fault handler: they must not take locks that could be held by the
+/// faulting code, only try them.
+pub type Resolver = fn(&PageFault) -> bool;
+
+static RESOLVERS: Mutex<[Option<Resolver>; MAX_RESOLVERS]> = Mutex::new([None; MAX_RESOLVERS]);
+
+/// Adds a resolver, tried after the ones already registered.
+pub fn register_resolver(resolver: Resolver) {
+    let mut resolvers = RESOLVERS.lock();
+    let slot = resolvers
+        .iter_mut()
+        .find(|r| r.is_none())
+        .expect("too many page fault resolvers");
+    *slot = Some(resolver);
+}
+
+// An exception table entry: a fault at `fault` resumes execution at `fixup`.
+#[repr(C)]
+struct Fixup {
+    fault: u64,
+    fixup: u64,
+}
+
+// Makes sure that the section exists even if nothing probes memory, it never matches.
+#[used]
+#[link_section = "ex_table"]
+static SENTINEL: Fixup = Fixup { fault: 0, fixup: 0 };
+
+extern "C" {
+    // defined by the linker
+    static __start_ex_table: Fixup;
+    static __stop_ex_table: Fixup;
+}
+
+fn find_fixup(ip: VirtAddr) -> Option<VirtAddr> {
+    let table = unsafe {
+        let start = &__start_ex_table as *const Fixup;
+        let end = &__stop_ex_table as *const Fixup;
+        core::slice::from_raw_parts(start, (end as usize - start as usize) / size_of::<Fixup>())
+    };
+    table
+        .iter()
+        .find(|entry| entry.fault == ip.as_u64())
+        .map(|entry| VirtAddr::new(entry.fixup))
+}
+
+/// Reads the byte at `addr`, or returns `None` if reading it faults.
+pub fn probe_read(addr: VirtAddr) -> Option<u8> {
+    let value: u32;
+    let failed: u32;
+    unsafe {
+        asm!(
+            "2: movzx {value:e}, byte ptr [{addr}]",
+            "xor {failed:e}, {failed:e}",
+            "jmp 4f",
+            "3: mov {failed:e}, 1",
+            "xor {value:e}, {value:e}",
+            "4:",
+            ".pushsection ex_table, \"a\"",
+            ".balign 8",
+            ".quad 2b, 3b",
+            ".popsection",
+            addr = in(reg) addr.as_u64(),
+            value = out(reg) value,
+            failed = out(reg) failed,
+            options(nostack, readonly),
+        );
+    }
+    if failed == 0 {
+        Some(value as u8)
+    } else {
+        None
+    }
+}
+
+/// Writes `value` at `addr`, returns false if writing it faults.
+pub fn probe_write(addr: VirtAddr, value: u8) -> bool {
+    let failed: u32;
+    unsafe {
+        asm!(
+            "2: mov byte ptr [{addr}], {value}",
+            "xor {failed:e}, {failed:e}",
+            "jmp 4f",
+            "3: mov {failed:e}, 1",
+            "4:",
+            ".pushsection ex_table, \"a\"",
+            ".balign 8",
+            ".quad 2b, 3b",
+            ".popsection",
+            addr = in(reg) addr.as_u64(),
+            value = in(reg_byte) value,
+            failed = out(reg) failed,
+            options(nostack),
+        );
+    }
+    failed == 0
+}
+
+// Called by the page fault's entry stub.
+#[no_mangle]
+extern "C" fn page_fault_handler(stack: &mut ExceptionStack) {
+    let fault = PageFault {
+        addr: Cr2::read(),
+        error_code: PageFaultErrorCode::from_bits_truncate(stack.error_code),
+        ip: stack.frame.instruction_pointer,
+    };
+
+    if let Some(overflowed) = crate::memory::stack::overflowed(fault.addr) {
+        panic!(
+            "stack overflow in {}\n{:#?}",
+            overflowed.name(),
+            stack.frame
+        );
+    }
+
+    // copied so that resolvers can register other resolvers
+    let resolvers = RESOLVERS.try_lock().map(|r| *r).unwrap_or_default();
+    if resolvers.iter().flatten().any(|resolve| resolve(&fault)) {
+        return;
+    }
+
+    if let Some(fixup) = find_fixup(fault.ip) {
+        unsafe { stack.frame.as_mut().instruction_pointer = fixup };
+        return;
+    }
+
+    exceptions::crash(&exceptions::PAGE_FAULT, ErrorCode::Page(fault), stack)
+}
@@ -0,0 +1,194 @@
+#![no_std]
+#![cfg_attr(test, no_main)]
+#![feature(alloc_error_handler)] // https://os.phil-opp.com/heap-allocation/#the-alloc-error-handler-attribute
+#![feature(const_in_array_repeat_expressions)] // https://os.phil-opp.com/allocator-designs/#implementation-2
+#![feature(const_mut_refs)] // https://os.phil-opp.com/allocator-designs/#implementation-1
+#![feature(custom_test_frameworks)]
+#![feature(abi_x86_interrupt)] // https://os.phil-opp.com/cpu-exceptions/
+#![feature(wake_trait)] // https://os.phil-opp.com/async-await/#the-wake-trait
+#![feature(cell_update)]
+#![feature(asm)]
+#![feature(global_asm)]
+#![test_runner(crate::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+
+pub mod acpi;
+pub mod allocator;
+pub mod cpu;
+pub mod gdt;
+pub mod interrupts;
+pub mod memory;
+pub mod qemu;
+#[macro_use]
+pub mod serial;
+pub mod task;
+pub mod time;
+pub mod vga_buffer;
+
+extern crate alloc;
+
+#[cfg(test)]
+bootloader::entry_point!(test_kernel_main);
+
+/// Entrypoint for cargo test
+#[cfg(test)]
+fn test_kernel_main(_: &'static bootloader::BootInfo) -> ! {
+    init();
+    test_main();
+    hlt();
+}
+
+pub fn init() {
+    cpu::init();
+    interrupts::init_idt();
+    interrupts::init_pics();
+    gdt::init_gdt();
+    time::init();
+    x86_64::instructions::interrupts::enable();
+}
+
+#[alloc_error_handler]
+fn alloc_error_handler(layout: alloc::alloc::Layout) -> ! {
+    panic!("allocation error: {:?}", layout)
+}
+
+pub trait Test {
+    fn run(&self) -> ();
+}
+
+impl<T> Test for T
+where
+    T: Fn(),
+{
+    fn run(&self) {
+        serial_print!("{}...\t", core::any::type_name::<T>());
+        self();
+        serial_println!("[ok]");
+    }
+}
+
+pub fn test_panic_handler(info: &PanicInfo) -> ! {
+    serial_println!("[failed]");
+    serial_println!("Error: {}", info);
+    qemu::exit(qemu::ExitCode::Failure);
+    hlt();
+}
+
+/// Panic handler of the tests that are expected to panic: they pass if the panic message contains
+/// `expected`.
+pub fn expected_panic_handler(info: &PanicInfo, expected: &str) -> ! {
+    let mut message = Prefix {
+        buf: [0; 1024],
+        len: 0,
+    };
+    let _ = write!(message, "{}", info);
+    if message.as_str().contains(expected) {
+        serial_println!("[ok]");
+        qemu::exit(qemu::ExitCode::Success);
+        hlt();
+    }
+    serial_println!("[failed]");
+    serial_println!("Error: {}", info);
+    serial_println!("Expected a panic with: {}", expected);
+    qemu::exit(qemu::ExitCode::Failure);
+    hlt();
+}
+
+// The beginning of what's written to it, the rest is dropped.
+struct Prefix {
+    buf: [u8; 1024],
+    len: usize,
+}
+
+impl Prefix {
+    fn as_str(&self) -> &str {
+        let bytes = &self.buf[..self.len];
+        match core::str::from_utf8(bytes) {
+            Ok(s) => s,
+            // truncated in the middle of a character
+            Err(e) => core::str::from_utf8(&bytes[..e.valid_up_to()]).unwrap(),
+        }
+    }
+}
+
+impl Write for Prefix {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let len = s.len().min(self.buf.len() - self.len);
+        self.buf[self.len..self.len + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.len += len;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    test_panic_handler(info)
+}
+
+pub fn test_runner(tests: &[&dyn Test]) {
+    serial_println!("Running {} tests", tests.len());
+    for test in tests {
+        test.run();
+    }
+    qemu::exit(qemu::ExitCode::Success);
+}
+
+pub fn hlt() -> ! {
+    loop {
+        x86_64::instructions::hlt();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test_case]
+    fn test_intr_bkpt() {
+        // invoke the breakpoint
+        x86_64::instructions::interrupts::int3();
+    }
+
+    #[test_case]
+    fn test_intr_bkpt_restores_registers() {
+        // the ones the handler is free to use, the entry stub restores them
+        let values: [u64; 9] = [1, 2, 3, 4, 5, 6, 7, 8, 9];
+        let mut registers = values;
+        unsafe {
+            asm!(
+                "int3",
+                inout("rax") registers[0],
+                inout("rcx") registers[1],
+                inout("rdx") registers[2],
+                inout("rsi") registers[3],
+                inout("rdi") registers[4],
+                inout("r8") registers[5],
+                inout("r9") registers[6],
+                inout("r10") registers[7],
+                inout("r11") registers[8],
+            );
+        }
+        assert_eq!(registers, values);
+    }
+
+    #[test_case]
+    fn test_supervisor_protections() {
+        use x86_64::registers::control::{Cr4, Cr4Flags};
+        let features = crate::cpu::features();
+        let cr4 = Cr4::read();
+        assert_eq!(
+            cr4.contains(Cr4Flags::SUPERVISOR_MODE_EXECUTION_PROTECTION),
+            features.smep
+        );
+        assert_eq!(
+            cr4.contains(Cr4Flags::SUPERVISOR_MODE_ACCESS_PREVENTION),
+            features.smap
+        );
+        assert_eq!(
+            cr4.contains(Cr4Flags::USER_MODE_INSTRUCTION_PREVENTION),
+            features.umip
+        );
+    }
+}
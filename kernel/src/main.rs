@@ -21,7 +21,28 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
     unsafe { philos::memory::init(boot_info) };
     philos::allocator::init().expect("heap allocation failed");
     let acpi = unsafe { philos::acpi::init() }.expect("unable to enable ACPI");
+    unsafe { philos::memory::reclaim_boot_memory(boot_info) };
+    match unsafe { philos::interrupts::apic::init(&acpi) } {
+        Ok(()) => println!("Interrupts     : APIC"),
+        Err(e) => println!("Interrupts     : PIC, no usable APIC ({:?})", e),
+    }
+    match unsafe { philos::time::hpet::init(&acpi) } {
+        Ok(hpet) => {
+            println!("HPET           : {} Hz", hpet.frequency());
+            match unsafe { philos::time::use_hpet(hpet) } {
+                Ok(()) => println!("Ticks          : HPET"),
+                Err(e) => println!("Ticks          : PIT ({:?})", e),
+            }
+            println!("TSC            : {} Hz", hpet.calibrate_tsc());
+            if let Some(local_apic) = philos::interrupts::apic::local_apic() {
+                let frequency = unsafe { hpet.calibrate_apic_timer(local_apic) };
+                println!("APIC timer     : {} Hz", frequency);
+            }
+        }
+        Err(e) => println!("HPET           : none ({:?})", e),
+    }
 
+    println!("CPU features   : {}", philos::cpu::features());
     println!("ACPI revision {}", acpi.revision);
     if let Ok(platform_info) = acpi.platform_info() {
         println!("Power profile  : {:?}", platform_info.power_profile);
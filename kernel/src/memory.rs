@@ -0,0 +1,186 @@
+use bootloader::BootInfo;
+use conquer_once::spin::OnceCell;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::Mutex;
+use x86_64::registers::control::Cr3;
+use x86_64::registers::model_specific::{Efer, EferFlags};
+use x86_64::structures::paging::frame::PhysFrameRange;
+use x86_64::structures::paging::{
+    OffsetPageTable, PageSize, PageTable, PageTableFlags, PhysFrame, Size4KiB,
+};
+use x86_64::{PhysAddr, VirtAddr};
+
+pub mod address_space;
+pub mod frame;
+mod image;
+pub mod inspect;
+pub mod lazy;
+pub mod mmio;
+pub mod stack;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod vmm;
+
+pub use address_space::AddressSpace;
+pub use frame::{BuddyFrameAllocator, FrameStats};
+pub use inspect::{dump_page_tables, translate};
+pub use lazy::LazyRegion;
+pub use mmio::{map_mmio, Caching, Mmio};
+
+pub static MAPPER: OnceCell<Mutex<OffsetPageTable<'static>>> = OnceCell::uninit();
+pub static FRAME_ALLOCATOR: OnceCell<Mutex<BuddyFrameAllocator>> = OnceCell::uninit();
+static PHYSICAL_MEMORY_OFFSET: OnceCell<VirtAddr> = OnceCell::uninit();
+// Entries in the bootloader's memory map.
+const MAX_BOOT_REGIONS: usize = 64;
+// Active page tables in the memory that's reclaimed after boot.
+const MAX_BOOT_TABLES: usize = 512;
+
+static BOOT_MEMORY_RECLAIMED: AtomicBool = AtomicBool::new(false);
+
+static NO_EXECUTE_ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub unsafe fn init(boot_info: &'static BootInfo) {
+    enable_no_execute();
+    let phys_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    PHYSICAL_MEMORY_OFFSET.init_once(|| phys_offset);
+    MAPPER.init_once(|| {
+        let level_4_table = active_level4_table(phys_offset);
+        Mutex::new(OffsetPageTable::new(level_4_table, phys_offset))
+    });
+    FRAME_ALLOCATOR.init_once(|| Mutex::new(BuddyFrameAllocator::new(&boot_info.memory_map)));
+
+    let physical_memory_size = boot_info
+        .memory_map
+        .iter()
+        .map(|r| r.range.end_addr())
+        .max()
+        .unwrap_or(0);
+    vmm::init(phys_offset, physical_memory_size).expect("invalid kernel memory layout");
+    image::remap();
+    address_space::init();
+    mmio::init();
+    stack::init();
+    lazy::init();
+}
+
+/// Gives the memory that was only needed to boot to the frame allocator, except for the page
+/// tables still in use, and returns the number of frames reclaimed.
+///
+/// Only the first call reclaims anything, later ones return 0 without reading `boot_info`.
+///
+/// Unsafe since `boot_info` is part of the reclaimed memory and can't be used anymore, nor can
+/// anything the bootloader set up besides the page tables and the kernel stack.
+pub unsafe fn reclaim_boot_memory(boot_info: &'static BootInfo) -> usize {
+    if BOOT_MEMORY_RECLAIMED.swap(true, Ordering::SeqCst) {
+        return 0;
+    }
+
+    // copied out first, freed frames get overwritten
+    let mut regions: [Option<PhysFrameRange>; MAX_BOOT_REGIONS] = [None; MAX_BOOT_REGIONS];
+    let reclaimable = boot_info
+        .memory_map
+        .iter()
+        .filter(|r| frame::is_reclaimable(r.region_type));
+    assert!(
+        reclaimable.clone().count() <= MAX_BOOT_REGIONS,
+        "too many regions of boot memory"
+    );
+    for (slot, region) in regions.iter_mut().zip(reclaimable) {
+        *slot = Some(PhysFrame::range(
+            PhysFrame::containing_address(PhysAddr::new(region.range.start_addr())),
+            PhysFrame::containing_address(PhysAddr::new(region.range.end_addr())),
+        ));
+    }
+
+    let _mapper = MAPPER.get().unwrap().lock();
+    // the active page tables within those regions, found in a single walk
+    let mut tables = [PhysFrame::containing_address(PhysAddr::new(0)); MAX_BOOT_TABLES];
+    let mut count = 0;
+    let in_regions = |frame: PhysFrame| {
+        regions
+            .iter()
+            .flatten()
+            .any(|region| region.start <= frame && frame < region.end)
+    };
+    inspect::for_each_page_table(|frame| {
+        if in_regions(frame) {
+            assert!(
+                count < MAX_BOOT_TABLES,
+                "too many page tables in boot memory"
+            );
+            tables[count] = frame;
+            count += 1;
+        }
+    });
+    let tables = &mut tables[..count];
+    tables.sort_unstable();
+
+    let mut frame_allocator = FRAME_ALLOCATOR.get().unwrap().lock();
+    let mut reclaimed = 0;
+    for region in regions.iter().flatten() {
+        // reclaims the runs of frames between the active page tables
+        let mut run_start = region.start;
+        let within = tables
+            .iter()
+            .filter(|&&table| region.start <= table && table < region.end);
+        for &table in within {
+            // tables are listed once per entry pointing at them
+            if table >= run_start {
+                frame_allocator.reclaim(PhysFrame::range(run_start, table));
+                reclaimed += table - run_start;
+                run_start = table + 1;
+            }
+        }
+        frame_allocator.reclaim(PhysFrame::range(run_start, region.end));
+        reclaimed += region.end - run_start;
+    }
+
+    crate::println!(
+        "Reclaimed {} KiB of boot memory",
+        reclaimed * Size4KiB::SIZE / 1024
+    );
+    reclaimed as usize
+}
+
+// Allows page table entries to set NO_EXECUTE, it's a reserved bit otherwise.
+unsafe fn enable_no_execute() {
+    if crate::cpu::features().nx {
+        Efer::update(|flags| flags.insert(EferFlags::NO_EXECUTE_ENABLE));
+        NO_EXECUTE_ENABLED.store(true, Ordering::Relaxed);
+    }
+}
+
+/// `NO_EXECUTE` if the CPU supports it, empty otherwise: every mapping of data should include it.
+pub fn no_execute() -> PageTableFlags {
+    if NO_EXECUTE_ENABLED.load(Ordering::Relaxed) {
+        PageTableFlags::NO_EXECUTE
+    } else {
+        PageTableFlags::empty()
+    }
+}
+
+/// Returns the address at which the bootloader mapped the physical address `addr`.
+pub fn phys_to_virt(addr: PhysAddr) -> VirtAddr {
+    let offset = PHYSICAL_MEMORY_OFFSET
+        .get()
+        .expect("memory module not initialized");
+    *offset + addr.as_u64()
+}
+
+/// Inverse of `phys_to_virt`, only valid for addresses within the physical memory mapping.
+pub fn virt_to_phys(addr: VirtAddr) -> PhysAddr {
+    let offset = PHYSICAL_MEMORY_OFFSET
+        .get()
+        .expect("memory module not initialized");
+    PhysAddr::new(addr - *offset)
+}
+
+unsafe fn active_level4_table(phys_memory_offset: VirtAddr) -> &'static mut PageTable {
+    let (level4_frame, _) = Cr3::read();
+
+    let phys_addr = level4_frame.start_address();
+    let virt = phys_memory_offset + phys_addr.as_u64();
+    let page_table_ptr: *mut PageTable = virt.as_mut_ptr();
+
+    &mut *page_table_ptr
+}
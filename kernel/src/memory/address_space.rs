@@ -0,0 +1,344 @@
+use super::vmm::VMM;
+use super::BuddyFrameAllocator;
+use crate::interrupts::page_fault::{self, PageFault};
+use conquer_once::spin::OnceCell;
+use core::ops::DerefMut;
+use x86_64::registers::control::Cr3;
+use x86_64::structures::paging::mapper::{FlagUpdateError, MapToError, MapperFlush, UnmapError};
+use x86_64::structures::paging::page_table::PageTableEntry;
+use x86_64::structures::paging::{
+    FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageSize, PageTable,
+    PageTableFlags, PageTableIndex, PhysFrame, Size4KiB,
+};
+
+/// Marks the pages that are shared read-only after a fork, and copied on the first write.
+pub const COPY_ON_WRITE: PageTableFlags = PageTableFlags::BIT_9;
+
+// The level 4 table that was active at boot, which the kernel keeps using.
+static KERNEL_LEVEL_4: OnceCell<PhysFrame> = OnceCell::uninit();
+
+unsafe fn table_at(frame: PhysFrame) -> &'static mut PageTable {
+    &mut *super::phys_to_virt(frame.start_address()).as_mut_ptr::<PageTable>()
+}
+
+fn kernel_level_4() -> &'static PageTable {
+    unsafe { table_at(*KERNEL_LEVEL_4.get().expect("memory module not initialized")) }
+}
+
+/// Whether `page` is part of the kernel, and so shared by every address space.
+pub fn is_kernel_page(page: Page) -> bool {
+    !kernel_level_4()[page.p4_index()].is_unused()
+}
+
+/// A set of page tables that maps the kernel like its own tables do, and whatever else privately.
+///
+/// The level 4 entries of the kernel are copied: the lower level tables behind them, and so the
+/// kernel's mappings, are shared. Every other page belongs to the address space, which owns the
+/// frames it maps: they're given back to the frame allocator along with the page tables when it's
+/// dropped.
+pub struct AddressSpace {
+    level_4_frame: PhysFrame,
+    mapper: OffsetPageTable<'static>,
+}
+
+impl AddressSpace {
+    /// Creates an address space that only maps the kernel.
+    pub fn new() -> Result<Self, MapToError<Size4KiB>> {
+        let _mapper = super::MAPPER.get().unwrap().lock();
+        let level_4_frame = super::FRAME_ALLOCATOR
+            .get()
+            .unwrap()
+            .lock()
+            .allocate_frame()
+            .ok_or(MapToError::FrameAllocationFailed)?;
+        let level_4 = unsafe { table_at(level_4_frame) };
+        level_4.zero();
+        for (entry, kernel_entry) in level_4.iter_mut().zip(kernel_level_4().iter()) {
+            if !kernel_entry.is_unused() {
+                entry.set_addr(kernel_entry.addr(), kernel_entry.flags());
+            }
+        }
+
+        let phys_offset = *super::PHYSICAL_MEMORY_OFFSET.get().unwrap();
+        Ok(AddressSpace {
+            level_4_frame,
+            mapper: unsafe { OffsetPageTable::new(level_4, phys_offset) },
+        })
+    }
+
+    /// Maps `page` to `frame`, allocating the page tables it needs.
+    ///
+    /// The address space takes ownership of `frame`, which must come from the frame allocator.
+    /// Unsafe for the same reasons as `Mapper::map_to`, the page must not be a kernel page.
+    pub unsafe fn map(
+        &mut self,
+        page: Page,
+        frame: PhysFrame,
+        flags: PageTableFlags,
+    ) -> Result<(), MapToError<Size4KiB>> {
+        assert!(!is_kernel_page(page), "{:?} belongs to the kernel", page);
+        let mut frame_allocator = super::FRAME_ALLOCATOR.get().unwrap().lock();
+        let flush = self.mapper.map_to(
+            page,
+            frame,
+            PageTableFlags::PRESENT | flags,
+            frame_allocator.deref_mut(),
+        )?;
+        self.flush(flush);
+        Ok(())
+    }
+
+    /// Unmaps `page` and returns the frame it was mapped to, along with its ownership.
+    pub fn unmap(&mut self, page: Page) -> Result<PhysFrame, UnmapError> {
+        assert!(!is_kernel_page(page), "{:?} belongs to the kernel", page);
+        let (frame, flush) = self.mapper.unmap(page)?;
+        self.flush(flush);
+        Ok(frame)
+    }
+
+    /// Changes the flags of a mapped page.
+    ///
+    /// Unsafe since it can break memory safety, e.g. by making borrowed memory read-only.
+    pub unsafe fn protect(
+        &mut self,
+        page: Page,
+        flags: PageTableFlags,
+    ) -> Result<(), FlagUpdateError> {
+        assert!(!is_kernel_page(page), "{:?} belongs to the kernel", page);
+        let flush = self
+            .mapper
+            .update_flags(page, PageTableFlags::PRESENT | flags)?;
+        self.flush(flush);
+        Ok(())
+    }
+
+    /// The frame that `page` is mapped to, kernel pages included.
+    pub fn translate_page(&self, page: Page) -> Option<PhysFrame> {
+        self.mapper.translate_page(page).ok()
+    }
+
+    /// Creates a copy of this address space that shares its frames.
+    ///
+    /// Writable pages become read-only and copy-on-write in both address spaces: the first write
+    /// to such a page copies it, unless no other address space still shares its frame.
+    pub fn fork(&mut self) -> Result<AddressSpace, MapToError<Size4KiB>> {
+        let mut child = AddressSpace::new()?;
+        let mut frame_allocator = super::FRAME_ALLOCATOR.get().unwrap().lock();
+        let forked = for_each_private(self.level_4_frame, |page, entry| {
+            let mut flags = entry.flags();
+            if flags.contains(PageTableFlags::WRITABLE) {
+                flags = (flags - PageTableFlags::WRITABLE) | COPY_ON_WRITE;
+                entry.set_flags(flags);
+            }
+            let frame = PhysFrame::containing_address(entry.addr());
+            frame_allocator.share(frame);
+            let mapped = unsafe {
+                child
+                    .mapper
+                    .map_to(page, frame, flags, frame_allocator.deref_mut())
+            };
+            match mapped {
+                // the child isn't active
+                Ok(flush) => flush.ignore(),
+                Err(e) => {
+                    unsafe { frame_allocator.deallocate_frame(frame) };
+                    return Err(e);
+                }
+            }
+            Ok(())
+        });
+        // dropping the child on error takes the lock again
+        drop(frame_allocator);
+        if self.is_active() {
+            x86_64::instructions::tlb::flush_all();
+        }
+        forked.map(|()| child)
+    }
+
+    pub fn is_active(&self) -> bool {
+        Cr3::read().0 == self.level_4_frame
+    }
+
+    /// Switches to this address space.
+    ///
+    /// Unsafe since every reference to memory that isn't part of the kernel becomes invalid.
+    pub unsafe fn activate(&self) {
+        let (_, flags) = Cr3::read();
+        Cr3::write(self.level_4_frame, flags);
+    }
+
+    // The TLB only needs flushing if the tables are in use.
+    fn flush(&self, flush: MapperFlush<Size4KiB>) {
+        if self.is_active() {
+            flush.flush();
+        } else {
+            flush.ignore();
+        }
+    }
+}
+
+impl Drop for AddressSpace {
+    fn drop(&mut self) {
+        if self.is_active() {
+            unsafe { activate_kernel() };
+        }
+        let mut frame_allocator = super::FRAME_ALLOCATOR.get().unwrap().lock();
+        let level_4 = unsafe { table_at(self.level_4_frame) };
+        for (entry, kernel_entry) in level_4.iter().zip(kernel_level_4().iter()) {
+            if kernel_entry.is_unused() && !entry.is_unused() {
+                let frame = PhysFrame::containing_address(entry.addr());
+                unsafe { free_table(frame, 3, &mut frame_allocator) };
+            }
+        }
+        unsafe { frame_allocator.deallocate_frame(self.level_4_frame) };
+    }
+}
+
+// Frees the table at `level` in `frame`, the tables below it and the frames they map.
+unsafe fn free_table(frame: PhysFrame, level: u8, frame_allocator: &mut BuddyFrameAllocator) {
+    for entry in table_at(frame).iter() {
+        if entry.flags().contains(PageTableFlags::PRESENT) {
+            let frame = PhysFrame::containing_address(entry.addr());
+            if level > 1 {
+                assert_not_huge(entry);
+                free_table(frame, level - 1, frame_allocator);
+            } else {
+                frame_allocator.deallocate_frame(frame);
+            }
+        }
+    }
+    frame_allocator.deallocate_frame(frame);
+}
+
+// Private mappings only use 4KiB pages, so entries above the P1 always point at a table.
+fn assert_not_huge(entry: &PageTableEntry) {
+    assert!(
+        !entry.flags().contains(PageTableFlags::HUGE_PAGE),
+        "huge page in a private mapping: {:?}",
+        entry
+    );
+}
+
+fn present_entries(
+    table: &'static mut PageTable,
+) -> impl Iterator<Item = (PageTableIndex, &'static mut PageTableEntry)> {
+    table
+        .iter_mut()
+        .enumerate()
+        .filter(|(_, entry)| entry.flags().contains(PageTableFlags::PRESENT))
+        .map(|(i, entry)| (PageTableIndex::new(i as u16), entry))
+}
+
+// Calls `f` with the level 1 entry of every page that isn't a kernel page, stops at the first
+// error.
+fn for_each_private<E>(
+    level_4_frame: PhysFrame,
+    mut f: impl FnMut(Page, &mut PageTableEntry) -> Result<(), E>,
+) -> Result<(), E> {
+    let kernel = kernel_level_4();
+    let table = |entry: &PageTableEntry| {
+        assert_not_huge(entry);
+        unsafe { table_at(PhysFrame::containing_address(entry.addr())) }
+    };
+    for (i4, entry4) in present_entries(unsafe { table_at(level_4_frame) }) {
+        if !kernel[i4].is_unused() {
+            continue;
+        }
+        for (i3, entry3) in present_entries(table(entry4)) {
+            for (i2, entry2) in present_entries(table(entry3)) {
+                for (i1, entry1) in present_entries(table(entry2)) {
+                    f(Page::from_page_table_indices(i4, i3, i2, i1), entry1)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// The level 1 entry of `page` in the active page tables, if there is one.
+unsafe fn active_entry(page: Page) -> Option<&'static mut PageTableEntry> {
+    let (mut frame, _) = Cr3::read();
+    let indexes = [page.p4_index(), page.p3_index(), page.p2_index()];
+    for &index in indexes.iter() {
+        let entry = &table_at(frame)[index];
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT) || flags.contains(PageTableFlags::HUGE_PAGE) {
+            return None;
+        }
+        frame = PhysFrame::containing_address(entry.addr());
+    }
+    Some(&mut table_at(frame)[page.p1_index()])
+}
+
+// Copies a copy-on-write page that's written to, or makes it writable if its frame isn't shared
+// anymore.
+fn resolve_copy_on_write(fault: &PageFault) -> bool {
+    let page = Page::containing_address(fault.addr);
+    if !fault.is_protection_violation() || !fault.is_write() || is_kernel_page(page) {
+        return false;
+    }
+    let entry = match unsafe { active_entry(page) } {
+        Some(entry) if entry.flags().contains(COPY_ON_WRITE) => entry,
+        _ => return false,
+    };
+    let mut frame_allocator = match super::FRAME_ALLOCATOR.get().unwrap().try_lock() {
+        Some(frame_allocator) => frame_allocator,
+        None => return false,
+    };
+
+    let flags = (entry.flags() - COPY_ON_WRITE) | PageTableFlags::WRITABLE;
+    let frame = PhysFrame::containing_address(entry.addr());
+    if frame_allocator.owners(frame) == 1 {
+        entry.set_flags(flags);
+    } else {
+        let copy: PhysFrame = match frame_allocator.allocate_frame() {
+            Some(copy) => copy,
+            None => return false,
+        };
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                super::phys_to_virt(frame.start_address()).as_ptr::<u8>(),
+                super::phys_to_virt(copy.start_address()).as_mut_ptr::<u8>(),
+                Size4KiB::SIZE as usize,
+            );
+            entry.set_frame(copy, flags);
+            frame_allocator.deallocate_frame(frame);
+        }
+    }
+    x86_64::instructions::tlb::flush(page.start_address());
+    true
+}
+
+/// Switches back to the kernel's own page tables.
+///
+/// Unsafe since every reference to memory that isn't part of the kernel becomes invalid.
+pub unsafe fn activate_kernel() {
+    let (_, flags) = Cr3::read();
+    Cr3::write(*KERNEL_LEVEL_4.get().unwrap(), flags);
+}
+
+// Makes sure that the kernel has a level 4 entry for each of its regions, so that the kernel
+// mappings created later are shared with the address spaces that already exist.
+pub(super) fn init() {
+    let (level_4_frame, _) = Cr3::read();
+    KERNEL_LEVEL_4.init_once(|| level_4_frame);
+
+    let vmm = VMM.lock();
+    let _mapper = super::MAPPER.get().unwrap().lock();
+    let mut frame_allocator = super::FRAME_ALLOCATOR.get().unwrap().lock();
+    let level_4 = unsafe { table_at(level_4_frame) };
+    for pages in vmm.regions() {
+        let first = u16::from(pages.start.p4_index()) as usize;
+        let last = u16::from((pages.end - 1).p4_index()) as usize;
+        for entry in level_4.iter_mut().take(last + 1).skip(first) {
+            if entry.is_unused() {
+                let frame = frame_allocator
+                    .allocate_frame()
+                    .expect("failed to allocate a kernel page table");
+                unsafe { table_at(frame).zero() };
+                entry.set_frame(frame, PageTableFlags::PRESENT | PageTableFlags::WRITABLE);
+            }
+        }
+    }
+    page_fault::register_resolver(resolve_copy_on_write);
+}
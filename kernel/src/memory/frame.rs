@@ -0,0 +1,353 @@
+use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use x86_64::structures::paging::frame::PhysFrameRange;
+use x86_64::structures::paging::{
+    FrameAllocator, FrameDeallocator, PageSize, PhysFrame, Size1GiB, Size2MiB, Size4KiB,
+};
+use x86_64::PhysAddr;
+
+/// Largest block handed out by the allocator: 2^18 4KiB frames, i.e. a 1GiB frame.
+pub const MAX_ORDER: usize = 18;
+const ORDERS: usize = MAX_ORDER + 1;
+
+const WORD_BITS: usize = 64;
+const NIL: usize = usize::MAX;
+
+// Written at the start of every free block, links the free blocks of the same order together.
+struct FreeBlock {
+    next: usize,
+    prev: usize,
+}
+
+/// Buddy-system physical frame allocator.
+///
+/// Free memory is kept in blocks of 2^order frames, each block being aligned to its own size.
+/// Free blocks are linked together through their own memory (accessed through the bootloader's
+/// physical memory mapping) and a bitmap per order records which blocks are free, so that a
+/// freed block can find and merge with its buddy in constant time.
+///
+/// A 4KiB frame can have several owners, see `share`: deallocating it then only drops one owner,
+/// the frame is freed with its last owner.
+pub struct BuddyFrameAllocator {
+    // the per-order bitmaps, one bit per block of that order, set when the block is free
+    bitmap: &'static mut [u64],
+    // per frame, the number of owners of an allocated frame besides the first one
+    shares: &'static mut [u16],
+    offsets: [usize; ORDERS],
+    heads: [usize; ORDERS],
+    free_blocks: [usize; ORDERS],
+    frames: usize,
+    total: usize,
+    free: usize,
+}
+
+/// A snapshot of the allocator's state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameStats {
+    /// Number of frames managed by the allocator.
+    pub total_frames: usize,
+    /// Number of frames currently available.
+    pub free_frames: usize,
+    /// Number of free blocks of each order.
+    pub free_blocks: [usize; ORDERS],
+}
+
+impl FrameStats {
+    /// The order of the largest free block, if any.
+    pub fn largest_free_order(&self) -> Option<usize> {
+        self.free_blocks.iter().rposition(|&count| count > 0)
+    }
+
+    /// Percentage of free memory that is not part of the largest free block.
+    ///
+    /// This is 0 when all free memory is in a single block and approaches 100 as free memory is
+    /// scattered in small blocks.
+    pub fn fragmentation(&self) -> usize {
+        match self.largest_free_order() {
+            None => 0,
+            Some(order) => 100 - (100 << order) / self.free_frames,
+        }
+    }
+}
+
+/// Whether memory of this type is only used until the kernel is done booting.
+///
+/// The bootloader's page tables aren't, when the kernel still uses them.
+pub fn is_reclaimable(region_type: MemoryRegionType) -> bool {
+    match region_type {
+        MemoryRegionType::Bootloader | MemoryRegionType::BootInfo | MemoryRegionType::PageTable => {
+            true
+        }
+        _ => false,
+    }
+}
+
+impl BuddyFrameAllocator {
+    /// Builds the allocator from the bootloader's memory map.
+    ///
+    /// The physical memory offset must already be known to the `memory` module.
+    pub(super) unsafe fn new(memory_map: &MemoryMap) -> Self {
+        let usable = || {
+            memory_map
+                .iter()
+                .filter(|r| r.region_type == MemoryRegionType::Usable)
+        };
+
+        // leaves room for the memory that's reclaimed after boot
+        let frames = memory_map
+            .iter()
+            .filter(|r| r.region_type == MemoryRegionType::Usable || is_reclaimable(r.region_type))
+            .map(|r| r.range.end_frame_number as usize)
+            .max()
+            .unwrap_or(0);
+        let mut offsets = [0; ORDERS];
+        let mut words = 0;
+        for (order, offset) in offsets.iter_mut().enumerate() {
+            *offset = words;
+            let blocks = (frames + (1 << order) - 1) >> order;
+            words += (blocks + WORD_BITS - 1) / WORD_BITS;
+        }
+        let frame_size = Size4KiB::SIZE as usize;
+        let bitmap_frames = (words * 8 + frames * 2 + frame_size - 1) / frame_size;
+
+        let region = usable()
+            .find(|r| {
+                (r.range.end_frame_number - r.range.start_frame_number) as usize >= bitmap_frames
+            })
+            .expect("no usable region can hold the frame allocator bitmap");
+        let bitmap_start = super::phys_to_virt(PhysAddr::new(region.range.start_addr()));
+        let bitmap = core::slice::from_raw_parts_mut(bitmap_start.as_mut_ptr::<u64>(), words);
+        for word in bitmap.iter_mut() {
+            *word = 0;
+        }
+        let shares_start = bitmap_start + words * 8;
+        let shares = core::slice::from_raw_parts_mut(shares_start.as_mut_ptr::<u16>(), frames);
+        for count in shares.iter_mut() {
+            *count = 0;
+        }
+
+        let mut allocator = BuddyFrameAllocator {
+            bitmap,
+            shares,
+            offsets,
+            heads: [NIL; ORDERS],
+            free_blocks: [0; ORDERS],
+            frames,
+            total: 0,
+            free: 0,
+        };
+        let bitmap_end = region.range.start_frame_number as usize + bitmap_frames;
+        for r in usable() {
+            let start = if r.range.start_frame_number == region.range.start_frame_number {
+                bitmap_end
+            } else {
+                r.range.start_frame_number as usize
+            };
+            allocator.add_range(start, r.range.end_frame_number as usize);
+        }
+        allocator.total = allocator.free;
+        allocator
+    }
+
+    /// Number of frames currently available for allocation.
+    pub fn free_frames(&self) -> usize {
+        self.free
+    }
+
+    pub fn stats(&self) -> FrameStats {
+        FrameStats {
+            total_frames: self.total,
+            free_frames: self.free,
+            free_blocks: self.free_blocks,
+        }
+    }
+
+    /// Adds an owner to an allocated frame, which then has to be deallocated once more before it's
+    /// actually freed.
+    pub fn share(&mut self, frame: PhysFrame) {
+        let index = frame_index(frame);
+        assert!(
+            index < self.frames && !self.is_free_frame(index),
+            "frame {:?} is not allocated",
+            frame
+        );
+        self.shares[index] = self.shares[index]
+            .checked_add(1)
+            .expect("too many owners of a frame");
+    }
+
+    /// Number of owners of an allocated frame.
+    pub fn owners(&self, frame: PhysFrame) -> usize {
+        self.shares[frame_index(frame)] as usize + 1
+    }
+
+    /// Allocates `count` physically contiguous frames.
+    ///
+    /// The first frame is aligned to the next power of two greater than or equal to `count`.
+    pub fn allocate_contiguous(&mut self, count: usize) -> Option<PhysFrameRange> {
+        assert!(count > 0, "cannot allocate an empty frame range");
+        let order = count.next_power_of_two().trailing_zeros() as usize;
+        if order > MAX_ORDER {
+            return None;
+        }
+        let start = self.allocate_block(order)?;
+        // give back the tail of the block
+        self.add_range(start + count, start + (1 << order));
+        Some(PhysFrame::range(frame_at(start), frame_at(start + count)))
+    }
+
+    /// Returns a range obtained from `allocate_contiguous` to the allocator.
+    pub unsafe fn deallocate_contiguous(&mut self, range: PhysFrameRange) {
+        let start = frame_index(range.start);
+        let end = frame_index(range.end);
+        for index in start..end {
+            assert!(
+                !self.is_free_frame(index),
+                "frame {} is not allocated",
+                index
+            );
+        }
+        self.add_range(start, end);
+    }
+
+    /// Adds frames that weren't usable at boot.
+    pub(super) unsafe fn reclaim(&mut self, range: PhysFrameRange) {
+        let start = frame_index(range.start);
+        let end = frame_index(range.end);
+        assert!(end <= self.frames, "reclaimed frames are out of range");
+        self.add_range(start, end);
+        self.total += end - start;
+    }
+
+    // Frees [start, end) as a sequence of the largest possible aligned blocks.
+    fn add_range(&mut self, mut start: usize, end: usize) {
+        while start < end {
+            let align = (start.trailing_zeros() as usize).min(MAX_ORDER);
+            let fit = WORD_BITS - 1 - (end - start).leading_zeros() as usize;
+            let order = align.min(fit);
+            self.free_block(start, order);
+            start += 1 << order;
+        }
+    }
+
+    fn allocate_block(&mut self, order: usize) -> Option<usize> {
+        let mut current = (order..ORDERS).find(|&o| self.heads[o] != NIL)?;
+        let index = self.heads[current];
+        self.unlink(current, index);
+        // split the block, giving back the upper halves
+        while current > order {
+            current -= 1;
+            self.link(current, index + (1 << current));
+        }
+        self.free -= 1 << order;
+        Some(index)
+    }
+
+    fn free_block(&mut self, mut index: usize, mut order: usize) {
+        debug_assert_eq!(index % (1 << order), 0);
+        self.free += 1 << order;
+        while order < MAX_ORDER {
+            let buddy = index ^ (1 << order);
+            if buddy >= self.frames || !self.is_free(order, buddy) {
+                break;
+            }
+            self.unlink(order, buddy);
+            index = index.min(buddy);
+            order += 1;
+        }
+        self.link(order, index);
+    }
+
+    // Whether the frame at `index` is part of any free block.
+    fn is_free_frame(&self, index: usize) -> bool {
+        (0..ORDERS).any(|order| self.is_free(order, index & !((1 << order) - 1)))
+    }
+
+    fn is_free(&self, order: usize, index: usize) -> bool {
+        let bit = index >> order;
+        self.bitmap[self.offsets[order] + bit / WORD_BITS] & (1 << (bit % WORD_BITS)) != 0
+    }
+
+    fn toggle(&mut self, order: usize, index: usize) {
+        let bit = index >> order;
+        self.bitmap[self.offsets[order] + bit / WORD_BITS] ^= 1 << (bit % WORD_BITS);
+    }
+
+    fn link(&mut self, order: usize, index: usize) {
+        let head = self.heads[order];
+        unsafe {
+            block(index).write(FreeBlock {
+                next: head,
+                prev: NIL,
+            });
+            if head != NIL {
+                (*block(head)).prev = index;
+            }
+        }
+        self.heads[order] = index;
+        self.free_blocks[order] += 1;
+        self.toggle(order, index);
+    }
+
+    fn unlink(&mut self, order: usize, index: usize) {
+        debug_assert!(self.is_free(order, index));
+        unsafe {
+            let FreeBlock { next, prev } = block(index).read();
+            if prev == NIL {
+                self.heads[order] = next;
+            } else {
+                (*block(prev)).next = next;
+            }
+            if next != NIL {
+                (*block(next)).prev = prev;
+            }
+        }
+        self.free_blocks[order] -= 1;
+        self.toggle(order, index);
+    }
+}
+
+fn block(index: usize) -> *mut FreeBlock {
+    super::phys_to_virt(frame_at::<Size4KiB>(index).start_address()).as_mut_ptr()
+}
+
+fn order_of<S: PageSize>() -> usize {
+    (S::SIZE / Size4KiB::SIZE).trailing_zeros() as usize
+}
+
+fn frame_at<S: PageSize>(index: usize) -> PhysFrame<S> {
+    PhysFrame::containing_address(PhysAddr::new(index as u64 * Size4KiB::SIZE))
+}
+
+fn frame_index<S: PageSize>(frame: PhysFrame<S>) -> usize {
+    (frame.start_address().as_u64() / Size4KiB::SIZE) as usize
+}
+
+macro_rules! impl_frame_allocator {
+    ($($size:ty),*) => {
+        $(
+            unsafe impl FrameAllocator<$size> for BuddyFrameAllocator {
+                fn allocate_frame(&mut self) -> Option<PhysFrame<$size>> {
+                    self.allocate_block(order_of::<$size>()).map(frame_at)
+                }
+            }
+
+            impl FrameDeallocator<$size> for BuddyFrameAllocator {
+                unsafe fn deallocate_frame(&mut self, frame: PhysFrame<$size>) {
+                    let index = frame_index(frame);
+                    assert!(
+                        index < self.frames && !self.is_free_frame(index),
+                        "frame {:?} is not allocated",
+                        frame
+                    );
+                    if self.shares[index] > 0 {
+                        self.shares[index] -= 1;
+                        return;
+                    }
+                    self.free_block(index, order_of::<$size>());
+                }
+            }
+        )*
+    };
+}
+
+impl_frame_allocator!(Size4KiB, Size2MiB, Size1GiB);
@@ -0,0 +1,85 @@
+//! Enforces W^X on the kernel image, using the permissions of its ELF segments rather than
+//! trusting however the bootloader mapped it.
+
+use core::mem::size_of;
+use x86_64::structures::paging::{Mapper, Page, PageTableFlags, Size4KiB};
+use x86_64::VirtAddr;
+
+const ELF_MAGIC: [u8; 4] = *b"\x7fELF";
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+
+// The fields of the ELF header that are needed to find the program headers.
+#[repr(C)]
+struct ElfHeader {
+    ident: [u8; 16],
+    _kind_to_entry: [u8; 16],
+    phoff: u64,
+    _shoff_to_ehsize: [u8; 14],
+    phentsize: u16,
+    phnum: u16,
+}
+
+#[repr(C)]
+struct ProgramHeader {
+    kind: u32,
+    flags: u32,
+    _offset: u64,
+    vaddr: u64,
+    _paddr: u64,
+    _filesz: u64,
+    memsz: u64,
+    _align: u64,
+}
+
+extern "C" {
+    // defined by the linker, the ELF header is part of the first loaded segment
+    static __ehdr_start: ElfHeader;
+}
+
+fn program_headers() -> &'static [ProgramHeader] {
+    let header = unsafe { &__ehdr_start };
+    assert_eq!(header.ident[..4], ELF_MAGIC, "kernel ELF header not mapped");
+    assert_eq!(header.phentsize as usize, size_of::<ProgramHeader>());
+    unsafe {
+        let start = (header as *const ElfHeader as *const u8).add(header.phoff as usize);
+        core::slice::from_raw_parts(start as *const ProgramHeader, header.phnum as usize)
+    }
+}
+
+fn segment_flags(segment: &ProgramHeader) -> PageTableFlags {
+    let writable = segment.flags & PF_W != 0;
+    let executable = segment.flags & PF_X != 0;
+    assert!(
+        !(writable && executable),
+        "kernel segment at {:#x} is writable and executable",
+        segment.vaddr
+    );
+    let mut flags = PageTableFlags::PRESENT;
+    if writable {
+        flags |= PageTableFlags::WRITABLE;
+    }
+    if !executable {
+        flags |= super::no_execute();
+    }
+    flags
+}
+
+// Segments start on their own page, so no page needs the permissions of two segments.
+pub(super) fn remap() {
+    let mut mapper = super::MAPPER.get().unwrap().lock();
+    for segment in program_headers().iter().filter(|s| s.kind == PT_LOAD) {
+        let flags = segment_flags(segment);
+        let start = VirtAddr::new(segment.vaddr);
+        let pages = Page::<Size4KiB>::range_inclusive(
+            Page::containing_address(start),
+            Page::containing_address(start + segment.memsz - 1u64),
+        );
+        for page in pages {
+            unsafe { mapper.update_flags(page, flags) }
+                .expect("failed to remap the kernel image")
+                .flush();
+        }
+    }
+}
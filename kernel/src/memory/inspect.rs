@@ -0,0 +1,306 @@
+//! Walks the active page tables, for debugging mappings without a debugger.
+//!
+//! The tables are read through the physical memory mapping, while holding `MAPPER` so that they
+//! don't change underneath.
+
+use core::fmt::{self, Write};
+use core::ops::Range;
+use x86_64::registers::control::Cr3;
+use x86_64::structures::paging::{PageTable, PageTableFlags, PhysFrame};
+use x86_64::{PhysAddr, VirtAddr};
+
+const LEVELS: usize = 4;
+
+// Bytes mapped by an entry at `level`, 1 being the level of 4KiB pages.
+fn entry_size(level: u8) -> u64 {
+    4096 << (9 * (level as u64 - 1))
+}
+
+fn size_name(size: u64) -> &'static str {
+    match size {
+        0x1000 => "4KiB",
+        0x20_0000 => "2MiB",
+        _ => "1GiB",
+    }
+}
+
+// Sign extends bit 47, entries in the upper half of the P4 map the top of the address space.
+fn canonical(addr: u64) -> u64 {
+    ((addr << 16) as i64 >> 16) as u64
+}
+
+unsafe fn table_at(addr: PhysAddr) -> &'static PageTable {
+    &*super::phys_to_virt(addr).as_ptr::<PageTable>()
+}
+
+// Consecutive pages of the same size and flags mapping consecutive frames.
+struct Run {
+    virt: u64,
+    phys: PhysAddr,
+    len: u64,
+    page_size: u64,
+    flags: PageTableFlags,
+}
+
+impl Run {
+    fn extends(&self, virt: u64, phys: PhysAddr, page_size: u64, flags: PageTableFlags) -> bool {
+        self.virt.wrapping_add(self.len) == virt
+            && self.phys + self.len == phys
+            && self.page_size == page_size
+            && self.flags == flags
+    }
+}
+
+impl fmt::Display for Run {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:#018x}-{:#018x} -> {:#x}-{:#x} {:>4} x {} {:?}",
+            self.virt,
+            self.virt + (self.len - 1),
+            self.phys.as_u64(),
+            (self.phys + (self.len - 1)).as_u64(),
+            self.len / self.page_size,
+            size_name(self.page_size),
+            self.flags
+        )
+    }
+}
+
+struct Dump<'a> {
+    range: Range<u64>,
+    run: Option<Run>,
+    out: &'a mut dyn Write,
+}
+
+impl Dump<'_> {
+    fn walk(&mut self, table: &PageTable, level: u8, base: u64) -> fmt::Result {
+        let size = entry_size(level);
+        for (i, entry) in table.iter().enumerate() {
+            let start = canonical(base + i as u64 * size);
+            let last = start + (size - 1);
+            if last < self.range.start || start >= self.range.end {
+                continue;
+            }
+            let flags = entry.flags();
+            if !flags.contains(PageTableFlags::PRESENT) {
+                self.flush()?;
+            } else if level == 1 || flags.contains(PageTableFlags::HUGE_PAGE) {
+                self.record(start, entry.addr(), size, flags)?;
+            } else {
+                self.walk(unsafe { table_at(entry.addr()) }, level - 1, start)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn record(
+        &mut self,
+        virt: u64,
+        phys: PhysAddr,
+        size: u64,
+        flags: PageTableFlags,
+    ) -> fmt::Result {
+        if let Some(run) = &mut self.run {
+            if run.extends(virt, phys, size, flags) {
+                run.len += size;
+                return Ok(());
+            }
+        }
+        self.flush()?;
+        self.run = Some(Run {
+            virt,
+            phys,
+            len: size,
+            page_size: size,
+            flags,
+        });
+        Ok(())
+    }
+
+    fn flush(&mut self) -> fmt::Result {
+        match self.run.take() {
+            Some(run) => writeln!(self.out, "{}", run),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Writes a line for every run of contiguous mappings that intersects `range`: first and last
+/// virtual and physical addresses, number and size of pages and flags.
+///
+/// Runs are printed whole, even if they extend past `range`.
+pub fn write_page_tables(range: Range<VirtAddr>, out: &mut dyn Write) -> fmt::Result {
+    let _mapper = super::MAPPER.get().unwrap().lock();
+    let (level4_frame, _) = Cr3::read();
+    let mut dump = Dump {
+        range: range.start.as_u64()..range.end.as_u64(),
+        run: None,
+        out,
+    };
+    dump.walk(unsafe { table_at(level4_frame.start_address()) }, 4, 0)?;
+    dump.flush()
+}
+
+/// Prints the mappings of `range` to the serial port, see `write_page_tables`.
+pub fn dump_page_tables(range: Range<VirtAddr>) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        write_page_tables(range, &mut *crate::serial::SERIAL.lock())
+            .expect("failed writing to serial interface")
+    });
+}
+
+// Calls `f` with the frame of every active page table, once per entry pointing at it. The caller
+// must hold `MAPPER`.
+pub(super) fn for_each_page_table(mut f: impl FnMut(PhysFrame)) {
+    fn walk(table: PhysAddr, level: u8, f: &mut dyn FnMut(PhysFrame)) {
+        f(PhysFrame::containing_address(table));
+        if level == 1 {
+            return;
+        }
+        for entry in unsafe { table_at(table) }.iter() {
+            let flags = entry.flags();
+            if flags.contains(PageTableFlags::PRESENT) && !flags.contains(PageTableFlags::HUGE_PAGE)
+            {
+                walk(entry.addr(), level - 1, f);
+            }
+        }
+    }
+    let (level4_frame, _) = Cr3::read();
+    walk(level4_frame.start_address(), 4, &mut f);
+}
+
+/// One level of a translation.
+#[derive(Debug, Clone, Copy)]
+pub struct Step {
+    /// 4 for the P4, down to 1 for the P1.
+    pub level: u8,
+    pub index: u16,
+    /// Physical address of the table.
+    pub table: PhysAddr,
+    /// The address held by the entry: the next table, or the frame.
+    pub addr: PhysAddr,
+    pub flags: PageTableFlags,
+}
+
+impl Step {
+    fn is_present(&self) -> bool {
+        self.flags.contains(PageTableFlags::PRESENT)
+    }
+
+    // Whether the entry maps a page rather than pointing at the next table.
+    fn maps_page(&self) -> bool {
+        self.is_present() && (self.level == 1 || self.flags.contains(PageTableFlags::HUGE_PAGE))
+    }
+}
+
+/// The walk of the page tables for an address, which `Display`s as an explanation of every
+/// level.
+#[derive(Debug, Clone, Copy)]
+pub struct Translation {
+    pub addr: VirtAddr,
+    steps: [Option<Step>; LEVELS],
+}
+
+impl Translation {
+    /// The levels that were walked, the last one maps a page or isn't present.
+    pub fn steps(&self) -> impl Iterator<Item = &Step> {
+        self.steps.iter().flatten()
+    }
+
+    fn last(&self) -> &Step {
+        self.steps().last().expect("translation without steps")
+    }
+
+    /// The physical address that `addr` translates to, if it's mapped.
+    pub fn phys_addr(&self) -> Option<PhysAddr> {
+        let last = self.last();
+        if last.maps_page() {
+            let offset = self.addr.as_u64() & (entry_size(last.level) - 1);
+            Some(last.addr + offset)
+        } else {
+            None
+        }
+    }
+
+    /// Size of the page that maps `addr`, if it's mapped.
+    pub fn page_size(&self) -> Option<u64> {
+        let last = self.last();
+        if last.maps_page() {
+            Some(entry_size(last.level))
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Translation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "translating {:#x}", self.addr.as_u64())?;
+        for step in self.steps() {
+            write!(
+                f,
+                "  P{}[{:>3}] of table at {:#x}: ",
+                step.level,
+                step.index,
+                step.table.as_u64()
+            )?;
+            if !step.is_present() {
+                writeln!(f, "not present, the walk stops here")?;
+            } else if step.maps_page() {
+                writeln!(
+                    f,
+                    "{} page at {:#x} {:?}",
+                    size_name(entry_size(step.level)),
+                    step.addr.as_u64(),
+                    step.flags
+                )?;
+            } else {
+                writeln!(
+                    f,
+                    "next table at {:#x} {:?}",
+                    step.addr.as_u64(),
+                    step.flags
+                )?;
+            }
+        }
+        match self.phys_addr() {
+            Some(phys) => write!(f, "  => {:#x}", phys.as_u64()),
+            None => write!(f, "  => not mapped"),
+        }
+    }
+}
+
+/// Walks the active page tables for `addr`, recording every level until a page or a missing
+/// entry is found.
+pub fn translate(addr: VirtAddr) -> Translation {
+    let _mapper = super::MAPPER.get().unwrap().lock();
+    let (level4_frame, _) = Cr3::read();
+    let indexes = [
+        u16::from(addr.p4_index()),
+        u16::from(addr.p3_index()),
+        u16::from(addr.p2_index()),
+        u16::from(addr.p1_index()),
+    ];
+    let mut translation = Translation {
+        addr,
+        steps: [None; LEVELS],
+    };
+    let mut table = level4_frame.start_address();
+    for (i, &index) in indexes.iter().enumerate() {
+        let entry = &unsafe { table_at(table) }[index as usize];
+        let step = Step {
+            level: (LEVELS - i) as u8,
+            index,
+            table,
+            addr: entry.addr(),
+            flags: entry.flags(),
+        };
+        translation.steps[i] = Some(step);
+        if !step.is_present() || step.maps_page() {
+            break;
+        }
+        table = step.addr;
+    }
+    translation
+}
@@ -0,0 +1,208 @@
+use super::vmm::{self, VmmError, VMM};
+use crate::interrupts::page_fault::{self, PageFault};
+use spin::Mutex;
+use x86_64::structures::paging::page::PageRange;
+use x86_64::structures::paging::{
+    FrameAllocator, FrameDeallocator, Mapper, Page, PageSize, PageTableFlags, PhysFrame, Size4KiB,
+};
+use x86_64::{PhysAddr, VirtAddr};
+
+const MAX_MAPPINGS: usize = 32;
+
+/// What the pages of a lazy mapping are backed by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backing {
+    /// Zeroed frames from the frame allocator, given back when unmapped.
+    Zero,
+    /// The physical memory starting at this address.
+    Physical(PhysAddr),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LazyError {
+    /// The pages are already mapped lazily.
+    Overlap,
+    TooManyMappings,
+    Vmm(VmmError),
+}
+
+impl From<VmmError> for LazyError {
+    fn from(error: VmmError) -> Self {
+        LazyError::Vmm(error)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct LazyMapping {
+    pages: PageRange,
+    backing: Backing,
+    flags: PageTableFlags,
+}
+
+static MAPPINGS: Mutex<[Option<LazyMapping>; MAX_MAPPINGS]> = Mutex::new([None; MAX_MAPPINGS]);
+
+/// Maps `pages` with `flags`, each page being mapped when it's first accessed.
+///
+/// Unsafe since the pages must not be mapped nor used for anything else until `unmap_lazy`, and
+/// physical backing memory must be safe to access.
+pub unsafe fn map_lazy(
+    pages: PageRange,
+    backing: Backing,
+    flags: PageTableFlags,
+) -> Result<(), LazyError> {
+    if let Backing::Physical(start) = backing {
+        assert!(start.is_aligned(Size4KiB::SIZE), "unaligned backing memory");
+    }
+    let mut mappings = MAPPINGS.lock();
+    let overlaps = |m: &LazyMapping| m.pages.start < pages.end && pages.start < m.pages.end;
+    if mappings.iter().flatten().any(overlaps) {
+        return Err(LazyError::Overlap);
+    }
+    let slot = mappings
+        .iter_mut()
+        .find(|m| m.is_none())
+        .ok_or(LazyError::TooManyMappings)?;
+    *slot = Some(LazyMapping {
+        pages,
+        backing,
+        flags,
+    });
+    Ok(())
+}
+
+/// Unmaps `pages`, previously given to `map_lazy`, and returns how many of them were mapped.
+///
+/// Unsafe since the pages must not be used anymore.
+pub unsafe fn unmap_lazy(pages: PageRange) -> u64 {
+    let mapping = {
+        let mut mappings = MAPPINGS.lock();
+        let slot = mappings
+            .iter_mut()
+            .find(|m| m.map_or(false, |m| m.pages == pages))
+            .expect("pages aren't mapped lazily");
+        slot.take().unwrap()
+    };
+
+    let mut mapper = super::MAPPER.get().unwrap().lock();
+    let mut frame_allocator = super::FRAME_ALLOCATOR.get().unwrap().lock();
+    let mut mapped = 0;
+    for page in pages {
+        if let Ok((frame, flush)) = mapper.unmap(page) {
+            flush.flush();
+            if mapping.backing == Backing::Zero {
+                frame_allocator.deallocate_frame(frame);
+            }
+            mapped += 1;
+        }
+    }
+    mapped
+}
+
+/// Number of pages of a lazy mapping that are currently mapped.
+pub fn mapped_pages(pages: PageRange) -> u64 {
+    let mapper = super::MAPPER.get().unwrap().lock();
+    pages
+        .filter(|&page| mapper.translate_page(page).is_ok())
+        .count() as u64
+}
+
+/// Virtual memory backed by zeroed frames only as it's touched, unmapped when dropped.
+#[derive(Debug)]
+pub struct LazyRegion {
+    pages: PageRange,
+}
+
+impl LazyRegion {
+    /// Reserves at least `size` bytes, without using any frame.
+    pub fn new(size: usize) -> Result<Self, LazyError> {
+        let pages = (size as u64 + Size4KiB::SIZE - 1) / Size4KiB::SIZE;
+        let pages = VMM.lock().allocate(vmm::LAZY, pages)?;
+        let flags = PageTableFlags::WRITABLE | super::no_execute();
+        if let Err(e) = unsafe { map_lazy(pages, Backing::Zero, flags) } {
+            VMM.lock()
+                .free(vmm::LAZY, pages)
+                .expect("failed to release virtual memory");
+            return Err(e);
+        }
+        Ok(LazyRegion { pages })
+    }
+
+    pub fn start(&self) -> VirtAddr {
+        self.pages.start.start_address()
+    }
+
+    pub fn len(&self) -> usize {
+        super::mmio::range_size(self.pages)
+    }
+
+    /// Number of pages that were touched, and so are backed by a frame.
+    pub fn resident_pages(&self) -> u64 {
+        mapped_pages(self.pages)
+    }
+}
+
+impl Drop for LazyRegion {
+    fn drop(&mut self) {
+        unsafe { unmap_lazy(self.pages) };
+        VMM.lock()
+            .free(vmm::LAZY, self.pages)
+            .expect("failed to release virtual memory");
+    }
+}
+
+fn resolve(fault: &PageFault) -> bool {
+    if fault.is_protection_violation() {
+        return false;
+    }
+    let page: Page = Page::containing_address(fault.addr);
+    let mapping = match MAPPINGS.try_lock().and_then(|mappings| {
+        mappings
+            .iter()
+            .flatten()
+            .find(|m| m.pages.start <= page && page < m.pages.end)
+            .copied()
+    }) {
+        Some(mapping) => mapping,
+        None => return false,
+    };
+
+    // the faulting code may hold these, in which case the fault can't be resolved
+    let (mut mapper, mut frame_allocator) = match (
+        super::MAPPER.get().unwrap().try_lock(),
+        super::FRAME_ALLOCATOR.get().unwrap().try_lock(),
+    ) {
+        (Some(mapper), Some(frame_allocator)) => (mapper, frame_allocator),
+        _ => return false,
+    };
+
+    let frame: PhysFrame = match mapping.backing {
+        Backing::Zero => match frame_allocator.allocate_frame() {
+            Some(frame) => {
+                let ptr = super::phys_to_virt(frame.start_address()).as_mut_ptr::<u8>();
+                unsafe { ptr.write_bytes(0, Size4KiB::SIZE as usize) };
+                frame
+            }
+            None => return false,
+        },
+        Backing::Physical(start) => {
+            PhysFrame::containing_address(start + (page - mapping.pages.start) * Size4KiB::SIZE)
+        }
+    };
+    let flags = mapping.flags | PageTableFlags::PRESENT;
+    match unsafe { mapper.map_to(page, frame, flags, &mut *frame_allocator) } {
+        Ok(flush) => {
+            flush.flush();
+            true
+        }
+        Err(_) => {
+            if mapping.backing == Backing::Zero {
+                unsafe { frame_allocator.deallocate_frame(frame) };
+            }
+            false
+        }
+    }
+}
+
+pub(super) fn init() {
+    page_fault::register_resolver(resolve);
+}
@@ -0,0 +1,209 @@
+use super::vmm::{self, VmmError};
+use core::ops::DerefMut;
+use core::sync::atomic::{AtomicBool, Ordering};
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::instructions::tlb;
+use x86_64::registers::control::{Cr0, Cr0Flags};
+use x86_64::registers::model_specific::Msr;
+use x86_64::structures::paging::mapper::MapToError;
+use x86_64::structures::paging::page::PageRange;
+use x86_64::structures::paging::{Mapper, Page, PageSize, PageTableFlags, PhysFrame, Size4KiB};
+use x86_64::{PhysAddr, VirtAddr};
+
+const IA32_PAT: u32 = 0x277;
+// The power-on default, except PA4 which is changed from write-back to write-combining.
+const PAT: u64 = 0x0007_0401_0007_0406;
+// In 4KiB page table entries, bit 7 selects the upper half of the PAT, it's only the huge page
+// flag in higher level tables.
+const PAT_FLAG: PageTableFlags = PageTableFlags::HUGE_PAGE;
+
+static PAT_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Memory type used for a mapping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Caching {
+    WriteBack,
+    WriteThrough,
+    /// Falls back to `Uncached` when the CPU doesn't support the PAT.
+    WriteCombining,
+    Uncached,
+}
+
+impl Caching {
+    fn flags(self) -> PageTableFlags {
+        match self {
+            Caching::WriteBack => PageTableFlags::empty(),
+            Caching::WriteThrough => PageTableFlags::WRITE_THROUGH,
+            Caching::WriteCombining if PAT_ENABLED.load(Ordering::Relaxed) => PAT_FLAG,
+            Caching::WriteCombining | Caching::Uncached => {
+                PageTableFlags::NO_CACHE | PageTableFlags::WRITE_THROUGH
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum MapError {
+    Vmm(VmmError),
+    Map(MapToError<Size4KiB>),
+}
+
+impl From<VmmError> for MapError {
+    fn from(error: VmmError) -> Self {
+        MapError::Vmm(error)
+    }
+}
+
+impl From<MapToError<Size4KiB>> for MapError {
+    fn from(error: MapToError<Size4KiB>) -> Self {
+        MapError::Map(error)
+    }
+}
+
+/// A mapping of device memory, unmapped when dropped.
+#[derive(Debug)]
+pub struct Mmio {
+    pages: PageRange,
+    start: VirtAddr,
+    len: usize,
+}
+
+impl Mmio {
+    /// Virtual address of the first mapped byte.
+    pub fn start(&self) -> VirtAddr {
+        self.start
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    fn contains(&self, offset: usize, size: usize) -> bool {
+        offset
+            .checked_add(size)
+            .map_or(false, |end| end <= self.len)
+    }
+
+    /// Volatile read of a `T` at `offset` bytes from the start of the mapping.
+    pub unsafe fn read<T: Copy>(&self, offset: usize) -> T {
+        assert!(self.contains(offset, core::mem::size_of::<T>()));
+        core::ptr::read_volatile((self.start + offset).as_ptr())
+    }
+
+    /// Volatile write of a `T` at `offset` bytes from the start of the mapping.
+    pub unsafe fn write<T: Copy>(&self, offset: usize, value: T) {
+        assert!(self.contains(offset, core::mem::size_of::<T>()));
+        core::ptr::write_volatile((self.start + offset).as_mut_ptr(), value)
+    }
+}
+
+impl Drop for Mmio {
+    fn drop(&mut self) {
+        unsafe { unmap_physical(vmm::MMIO, self.pages) }
+    }
+}
+
+/// Maps `len` bytes of device memory starting at `phys` using the requested memory type.
+pub unsafe fn map_mmio(phys: PhysAddr, len: usize, caching: Caching) -> Result<Mmio, MapError> {
+    let flags = PageTableFlags::WRITABLE | super::no_execute() | caching.flags();
+    let (pages, start) = map_physical(vmm::MMIO, phys, len, flags)?;
+    Ok(Mmio { pages, start, len })
+}
+
+/// Programs the PAT so that write-combining mappings are available.
+pub(super) fn init() {
+    if crate::cpu::features().pat {
+        without_interrupts(|| unsafe { write_pat(PAT) });
+        PAT_ENABLED.store(true, Ordering::Relaxed);
+    }
+}
+
+// Follows the SDM's sequence for changing memory types: no cache line may be left with the old
+// type, interrupts must be disabled.
+unsafe fn write_pat(pat: u64) {
+    let cr0 = Cr0::read();
+    let mut disabled = cr0;
+    disabled.insert(Cr0Flags::CACHE_DISABLE);
+    disabled.remove(Cr0Flags::NOT_WRITE_THROUGH);
+    Cr0::write(disabled);
+    asm!("wbinvd", options(nostack));
+    tlb::flush_all();
+
+    Msr::new(IA32_PAT).write(pat);
+
+    asm!("wbinvd", options(nostack));
+    tlb::flush_all();
+    Cr0::write(cr0);
+}
+
+/// Maps the physical memory `[phys, phys + len)` into virtual memory allocated from the named
+/// region.
+///
+/// Returns the mapped pages and the virtual address of `phys`.
+pub(crate) unsafe fn map_physical(
+    region: &'static str,
+    phys: PhysAddr,
+    len: usize,
+    flags: PageTableFlags,
+) -> Result<(PageRange, VirtAddr), MapError> {
+    assert!(len > 0, "cannot map an empty region");
+    let first_frame: PhysFrame = PhysFrame::containing_address(phys);
+    let last_frame: PhysFrame = PhysFrame::containing_address(phys + (len - 1));
+    let frames = PhysFrame::range_inclusive(first_frame, last_frame);
+    let pages = vmm::VMM
+        .lock()
+        .allocate(region, last_frame - first_frame + 1)?;
+
+    let mut mapper = super::MAPPER.get().unwrap().lock();
+    let mut frame_allocator = super::FRAME_ALLOCATOR.get().unwrap().lock();
+    for (page, frame) in pages.zip(frames) {
+        let mapped = mapper.map_to(
+            page,
+            frame,
+            PageTableFlags::PRESENT | flags,
+            frame_allocator.deref_mut(),
+        );
+        match mapped {
+            Ok(flush) => flush.flush(),
+            Err(e) => {
+                drop(frame_allocator);
+                drop(mapper);
+                unmap_pages(Page::range(pages.start, page));
+                release(region, pages);
+                return Err(e.into());
+            }
+        }
+    }
+
+    let start = pages.start.start_address() + (phys - first_frame.start_address());
+    Ok((pages, start))
+}
+
+/// Unmaps pages mapped by `map_physical` and returns them to the named region.
+pub(crate) unsafe fn unmap_physical(region: &'static str, pages: PageRange) {
+    unmap_pages(pages);
+    release(region, pages);
+}
+
+// The frames are left alone, they don't belong to the frame allocator.
+unsafe fn unmap_pages(pages: PageRange) {
+    let mut mapper = super::MAPPER.get().unwrap().lock();
+    for page in pages {
+        match mapper.unmap(page) {
+            Ok((_, flush)) => flush.flush(),
+            Err(e) => panic!("failed to unmap {:?}: {:?}", page, e),
+        }
+    }
+}
+
+fn release(region: &'static str, pages: PageRange) {
+    vmm::VMM
+        .lock()
+        .free(region, pages)
+        .expect("failed to release virtual memory");
+}
+
+/// Size in bytes of `pages`.
+pub(crate) fn range_size(pages: PageRange) -> usize {
+    ((pages.end - pages.start) * Size4KiB::SIZE) as usize
+}
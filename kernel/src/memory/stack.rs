@@ -0,0 +1,148 @@
+use super::mmio::MapError;
+use super::vmm::{self, VMM};
+use crate::gdt;
+use core::ops::DerefMut;
+use spin::Mutex;
+use x86_64::structures::paging::mapper::MapToError;
+use x86_64::structures::paging::{
+    FrameAllocator, FrameDeallocator, Mapper, Page, PageTableFlags, Size4KiB,
+};
+use x86_64::VirtAddr;
+
+const MAX_STACKS: usize = 32;
+const INTERRUPT_STACK_PAGES: u64 = 5;
+// How far from the stack pointer the kernel stack's ends are looked for.
+const MAX_KERNEL_STACK_PAGES: u64 = 1024;
+
+/// A kernel stack with an unmapped guard page below it.
+#[derive(Debug, Clone, Copy)]
+pub struct Stack {
+    name: &'static str,
+    guard: Page,
+    top: VirtAddr,
+}
+
+impl Stack {
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The unmapped page right below the stack.
+    pub fn guard(&self) -> Page {
+        self.guard
+    }
+
+    /// Initial value of the stack pointer.
+    pub fn top(&self) -> VirtAddr {
+        self.top
+    }
+}
+
+// Every stack ever allocated, so that the fault handlers can name the one that overflowed.
+static STACKS: Mutex<[Option<Stack>; MAX_STACKS]> = Mutex::new([None; MAX_STACKS]);
+
+/// Allocates and maps a stack of `pages` pages in the stacks region.
+///
+/// Stacks are never freed.
+pub fn allocate(name: &'static str, pages: u64) -> Result<Stack, MapError> {
+    let mut stacks = STACKS.lock();
+    let slot = stacks
+        .iter_mut()
+        .find(|s| s.is_none())
+        .expect("too many kernel stacks");
+
+    let range = VMM.lock().allocate(vmm::STACKS, pages + 1)?;
+    let guard = range.start;
+    let mut mapper = super::MAPPER.get().unwrap().lock();
+    let mut frame_allocator = super::FRAME_ALLOCATOR.get().unwrap().lock();
+    for page in Page::range(guard + 1, range.end) {
+        let mapped = frame_allocator
+            .allocate_frame()
+            .ok_or(MapToError::FrameAllocationFailed)
+            .and_then(|frame| unsafe {
+                let mapped = mapper.map_to(
+                    page,
+                    frame,
+                    PageTableFlags::PRESENT | PageTableFlags::WRITABLE | super::no_execute(),
+                    frame_allocator.deref_mut(),
+                );
+                if mapped.is_err() {
+                    frame_allocator.deallocate_frame(frame);
+                }
+                mapped
+            });
+        match mapped {
+            Ok(flush) => flush.flush(),
+            Err(e) => {
+                // give back what was mapped so far
+                for page in Page::range(guard + 1, page) {
+                    let (frame, flush) = mapper.unmap(page).expect("failed to unmap stack");
+                    flush.flush();
+                    unsafe { frame_allocator.deallocate_frame(frame) };
+                }
+                VMM.lock()
+                    .free(vmm::STACKS, range)
+                    .expect("failed to release virtual memory");
+                return Err(e.into());
+            }
+        }
+    }
+
+    let stack = Stack {
+        name,
+        guard,
+        top: range.end.start_address(),
+    };
+    *slot = Some(stack);
+    Ok(stack)
+}
+
+/// Returns the stack whose guard page contains `addr`.
+///
+/// This is called by the page and double fault handlers, which may have interrupted `allocate`, so
+/// the registry is only tried.
+pub fn overflowed(addr: VirtAddr) -> Option<Stack> {
+    let page = Page::<Size4KiB>::containing_address(addr);
+    STACKS
+        .try_lock()?
+        .iter()
+        .flatten()
+        .find(|stack| stack.guard == page)
+        .copied()
+}
+
+// The stack the bootloader started the kernel on, found from the stack pointer: the bootloader
+// maps it between two unmapped pages, the lower one being its guard page.
+fn kernel_stack() -> Option<Stack> {
+    let rsp: u64;
+    unsafe { asm!("mov {}, rsp", out(reg) rsp, options(nomem, nostack)) };
+    let current = Page::<Size4KiB>::containing_address(VirtAddr::new(rsp));
+    let mapper = super::MAPPER.get().unwrap().lock();
+    let unmapped = |page: &Page| mapper.translate_page(*page).is_err();
+    let guard = (1..MAX_KERNEL_STACK_PAGES)
+        .map(|i| current - i)
+        .find(unmapped)?;
+    let end = (1..MAX_KERNEL_STACK_PAGES)
+        .map(|i| current + i)
+        .find(unmapped)?;
+    Some(Stack {
+        name: "kernel stack",
+        guard,
+        top: end.start_address(),
+    })
+}
+
+// Registers the kernel stack, must be called on it, and replaces the interrupt stack that was set
+// up before memory was available.
+pub(super) fn init() {
+    let kernel_stack = kernel_stack().expect("failed to find the kernel stack");
+    *STACKS
+        .lock()
+        .iter_mut()
+        .find(|s| s.is_none())
+        .expect("too many kernel stacks") = Some(kernel_stack);
+
+    let stack = allocate("double fault stack", INTERRUPT_STACK_PAGES)
+        .expect("failed to allocate an interrupt stack");
+    unsafe { gdt::set_interrupt_stack(gdt::DOUBLE_FAULT_IST_INDEX, stack.top()) };
+}
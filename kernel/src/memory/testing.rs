@@ -0,0 +1,32 @@
+//! Helpers for the tests that count or juggle frames, each takes `FRAME_ALLOCATOR` for a single
+//! call.
+
+use super::FRAME_ALLOCATOR;
+use x86_64::structures::paging::{FrameAllocator, FrameDeallocator, PhysFrame};
+
+pub fn free_frames() -> usize {
+    FRAME_ALLOCATOR.get().unwrap().lock().free_frames()
+}
+
+/// Panics if there's no free frame.
+pub fn allocate_frame() -> PhysFrame {
+    FRAME_ALLOCATOR
+        .get()
+        .unwrap()
+        .lock()
+        .allocate_frame()
+        .expect("no free frame")
+}
+
+/// Frees a frame from `allocate_frame`.
+///
+/// # Safety
+///
+/// The frame mustn't be mapped or used anymore.
+pub unsafe fn deallocate_frame(frame: PhysFrame) {
+    FRAME_ALLOCATOR
+        .get()
+        .unwrap()
+        .lock()
+        .deallocate_frame(frame)
+}
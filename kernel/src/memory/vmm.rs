@@ -0,0 +1,259 @@
+use spin::Mutex;
+use x86_64::structures::paging::page::PageRange;
+use x86_64::structures::paging::{Page, PageSize, Size4KiB};
+use x86_64::VirtAddr;
+
+pub const PHYSICAL_MEMORY: &str = "physical memory";
+pub const ACPI: &str = "acpi";
+pub const HEAP: &str = "heap";
+pub const MMIO: &str = "mmio";
+pub const STACKS: &str = "stacks";
+pub const LAZY: &str = "lazy";
+
+// The kernel's virtual memory layout, besides the physical memory mapping which is chosen by the
+// bootloader.
+const LAYOUT: &[(&str, u64, u64)] = &[
+    (ACPI, 0x_3333_3333_0000, 256 * 1024 * 1024),
+    (HEAP, 0x_4444_4444_0000, 64 * 1024 * 1024),
+    (MMIO, 0x_5555_5555_0000, 1024 * 1024 * 1024),
+    (STACKS, 0x_6666_6666_0000, 64 * 1024 * 1024),
+    (LAZY, 0x_7777_7777_0000, 16 * 1024 * 1024 * 1024),
+];
+
+const MAX_REGIONS: usize = 16;
+const MAX_SPANS: usize = 64;
+
+pub static VMM: Mutex<Vmm> = Mutex::new(Vmm::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmmError {
+    /// The requested range overlaps the named region.
+    Overlap(&'static str),
+    /// No region has this name.
+    UnknownRegion,
+    /// The region doesn't have enough contiguous free space.
+    OutOfSpace,
+    /// The range doesn't belong to the region or was already freed.
+    InvalidRange,
+    /// The region's free space is too fragmented to be tracked.
+    TooFragmented,
+    /// Every region slot is taken.
+    TooManyRegions,
+    /// The bootloader didn't map any physical memory.
+    NoPhysicalMemory,
+}
+
+// A free range of virtual addresses, [start, end)
+#[derive(Debug, Clone, Copy)]
+struct Span {
+    start: u64,
+    end: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Region {
+    name: &'static str,
+    start: u64,
+    end: u64,
+    // free spans, sorted by address, never adjacent to each other
+    free: [Span; MAX_SPANS],
+    spans: usize,
+}
+
+impl Region {
+    fn allocate(&mut self, size: u64, align: u64) -> Result<u64, VmmError> {
+        for i in 0..self.spans {
+            let span = self.free[i];
+            let start = align_up(span.start, align);
+            if start.checked_add(size).map_or(true, |end| end > span.end) {
+                continue;
+            }
+            let end = start + size;
+            // carve [start, end) out of the span, leaving up to two spans
+            match (start > span.start, end < span.end) {
+                (false, false) => self.remove(i),
+                (true, false) => self.free[i].end = start,
+                (false, true) => self.free[i].start = end,
+                (true, true) => {
+                    self.insert(
+                        i + 1,
+                        Span {
+                            start: end,
+                            end: span.end,
+                        },
+                    )?;
+                    self.free[i].end = start;
+                }
+            }
+            return Ok(start);
+        }
+        Err(VmmError::OutOfSpace)
+    }
+
+    fn free(&mut self, start: u64, end: u64) -> Result<(), VmmError> {
+        if start < self.start || end > self.end || start >= end {
+            return Err(VmmError::InvalidRange);
+        }
+        // index of the first span after the freed range
+        let i = self.free[..self.spans]
+            .iter()
+            .position(|s| s.start >= end)
+            .unwrap_or(self.spans);
+        if i > 0 && self.free[i - 1].end > start {
+            // overlaps free space: double free
+            return Err(VmmError::InvalidRange);
+        }
+        let merge_prev = i > 0 && self.free[i - 1].end == start;
+        let merge_next = i < self.spans && self.free[i].start == end;
+
+        match (merge_prev, merge_next) {
+            (true, true) => {
+                self.free[i - 1].end = self.free[i].end;
+                self.remove(i);
+            }
+            (true, false) => self.free[i - 1].end = end,
+            (false, true) => self.free[i].start = start,
+            (false, false) => self.insert(i, Span { start, end })?,
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, index: usize, span: Span) -> Result<(), VmmError> {
+        if self.spans == MAX_SPANS {
+            return Err(VmmError::TooFragmented);
+        }
+        self.free.copy_within(index..self.spans, index + 1);
+        self.free[index] = span;
+        self.spans += 1;
+        Ok(())
+    }
+
+    fn remove(&mut self, index: usize) {
+        self.free.copy_within(index + 1..self.spans, index);
+        self.spans -= 1;
+    }
+}
+
+/// Kernel virtual address space manager.
+///
+/// The kernel's virtual address space is split into named, non-overlapping regions. Ranges of
+/// pages can then be allocated from a region and freed back to it for reuse.
+pub struct Vmm {
+    regions: [Option<Region>; MAX_REGIONS],
+}
+
+impl Vmm {
+    pub const fn new() -> Self {
+        Vmm {
+            regions: [None; MAX_REGIONS],
+        }
+    }
+
+    /// Reserves `range` under `name`, failing if it overlaps an existing region.
+    pub fn reserve(&mut self, name: &'static str, range: PageRange) -> Result<(), VmmError> {
+        let start = range.start.start_address().as_u64();
+        let end = range.end.start_address().as_u64();
+        for region in self.regions.iter().flatten() {
+            if start < region.end && region.start < end {
+                return Err(VmmError::Overlap(region.name));
+            }
+        }
+
+        let slot = self
+            .regions
+            .iter_mut()
+            .find(|r| r.is_none())
+            .ok_or(VmmError::TooManyRegions)?;
+        let mut free = [Span { start: 0, end: 0 }; MAX_SPANS];
+        free[0] = Span { start, end };
+        *slot = Some(Region {
+            name,
+            start,
+            end,
+            free,
+            spans: 1,
+        });
+        Ok(())
+    }
+
+    /// The pages covered by the named region.
+    pub fn region(&self, name: &str) -> Option<PageRange> {
+        self.regions
+            .iter()
+            .flatten()
+            .find(|r| r.name == name)
+            .map(|r| Page::range(page_at(r.start), page_at(r.end)))
+    }
+
+    /// The pages covered by every region.
+    pub fn regions(&self) -> impl Iterator<Item = PageRange> + '_ {
+        self.regions
+            .iter()
+            .flatten()
+            .map(|r| Page::range(page_at(r.start), page_at(r.end)))
+    }
+
+    /// Allocates `pages` contiguous pages from the named region.
+    pub fn allocate(&mut self, name: &str, pages: u64) -> Result<PageRange, VmmError> {
+        self.allocate_aligned(name, pages, Size4KiB::SIZE)
+    }
+
+    /// Allocates `pages` contiguous pages from the named region, the first one being aligned to
+    /// `align` bytes.
+    pub fn allocate_aligned(
+        &mut self,
+        name: &str,
+        pages: u64,
+        align: u64,
+    ) -> Result<PageRange, VmmError> {
+        let start = self
+            .find(name)?
+            .allocate(pages * Size4KiB::SIZE, align.max(Size4KiB::SIZE))?;
+        let start = page_at(start);
+        Ok(Page::range(start, start + pages))
+    }
+
+    /// Returns `range`, previously allocated from the named region, to that region.
+    pub fn free(&mut self, name: &str, range: PageRange) -> Result<(), VmmError> {
+        self.find(name)?.free(
+            range.start.start_address().as_u64(),
+            range.end.start_address().as_u64(),
+        )
+    }
+
+    fn find(&mut self, name: &str) -> Result<&mut Region, VmmError> {
+        self.regions
+            .iter_mut()
+            .flatten()
+            .find(|r| r.name == name)
+            .ok_or(VmmError::UnknownRegion)
+    }
+}
+
+/// Reserves the kernel's regions.
+///
+/// `physical_memory_size` is the size of the bootloader's mapping of physical memory, which is
+/// reserved so that no other region can overlap it.
+pub(super) fn init(phys_offset: VirtAddr, physical_memory_size: u64) -> Result<(), VmmError> {
+    if physical_memory_size == 0 {
+        return Err(VmmError::NoPhysicalMemory);
+    }
+    let mut vmm = VMM.lock();
+    let phys_start = Page::containing_address(phys_offset);
+    let phys_end = Page::containing_address(phys_offset + (physical_memory_size - 1)) + 1;
+    vmm.reserve(PHYSICAL_MEMORY, Page::range(phys_start, phys_end))?;
+
+    for &(name, start, size) in LAYOUT {
+        let start = page_at(start);
+        vmm.reserve(name, Page::range(start, start + size / Size4KiB::SIZE))?;
+    }
+    Ok(())
+}
+
+fn page_at(addr: u64) -> Page {
+    Page::containing_address(VirtAddr::new(addr))
+}
+
+fn align_up(addr: u64, align: u64) -> u64 {
+    (addr + align - 1) & !(align - 1)
+}
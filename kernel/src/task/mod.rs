@@ -7,6 +7,7 @@ use core::task::{Context, Poll};
 pub mod executor;
 pub mod keyboard;
 pub mod simple_executor;
+pub mod timer;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 struct TaskId(u64);
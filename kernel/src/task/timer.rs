@@ -0,0 +1,218 @@
+//! Futures that complete after some time, woken by the timer interrupt.
+//!
+//! Pending timers are kept in a hashed timer wheel: a timer sits in the slot of its deadline
+//! modulo the number of slots, and every tick only looks at the slot of that tick. The interrupt
+//! handler neither allocates nor frees, timers are added and removed by their futures and the
+//! handler only wakes them.
+
+use crate::time;
+use alloc::vec::Vec;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::task::{Context, Poll, Waker};
+use core::time::Duration;
+use futures_util::stream::Stream;
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+const SLOTS: usize = 256;
+
+struct Timer {
+    id: u64,
+    deadline: u64,
+    waker: Waker,
+    woken: bool,
+}
+
+struct Wheel {
+    slots: [Vec<Timer>; SLOTS],
+}
+
+fn slot(deadline: u64) -> usize {
+    (deadline % SLOTS as u64) as usize
+}
+
+impl Wheel {
+    const fn new() -> Self {
+        const EMPTY: Vec<Timer> = Vec::new();
+        Wheel {
+            slots: [EMPTY; SLOTS],
+        }
+    }
+
+    // Adds a timer, or updates its waker if it's already there.
+    fn insert(&mut self, id: u64, deadline: u64, waker: &Waker) {
+        let slot = &mut self.slots[slot(deadline)];
+        match slot.iter_mut().find(|timer| timer.id == id) {
+            Some(timer) if timer.waker.will_wake(waker) => {}
+            Some(timer) => timer.waker = waker.clone(),
+            None => slot.push(Timer {
+                id,
+                deadline,
+                waker: waker.clone(),
+                woken: false,
+            }),
+        }
+    }
+
+    fn remove(&mut self, id: u64, deadline: u64) {
+        let slot = &mut self.slots[slot(deadline)];
+        if let Some(i) = slot.iter().position(|timer| timer.id == id) {
+            slot.swap_remove(i);
+        }
+    }
+
+    fn expire(&mut self, now: u64) {
+        for timer in self.slots[slot(now)].iter_mut() {
+            if timer.deadline <= now && !timer.woken {
+                timer.woken = true;
+                timer.waker.wake_by_ref();
+            }
+        }
+    }
+}
+
+// Only locked with interrupts disabled.
+static WHEEL: Mutex<Wheel> = Mutex::new(Wheel::new());
+
+// Wakes the timers that are due, called by the timer interrupt.
+pub(crate) fn expire(now: u64) {
+    WHEEL.lock().expire(now);
+}
+
+/// A future that completes once a tick is reached.
+pub struct Sleep {
+    id: u64,
+    deadline: u64,
+    // whether it's in the wheel
+    registered: bool,
+}
+
+/// Completes after `duration` at least.
+pub fn sleep(duration: Duration) -> Sleep {
+    sleep_until(time::ticks() + time::duration_to_ticks(duration))
+}
+
+/// Completes once `time::ticks()` reaches `deadline`.
+pub fn sleep_until(deadline: u64) -> Sleep {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+    Sleep {
+        id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+        deadline,
+        registered: false,
+    }
+}
+
+impl Sleep {
+    /// The tick it completes at.
+    pub fn deadline(&self) -> u64 {
+        self.deadline
+    }
+
+    fn reset(&mut self, deadline: u64) {
+        self.unregister();
+        self.deadline = deadline;
+    }
+
+    fn unregister(&mut self) {
+        if self.registered {
+            without_interrupts(|| WHEEL.lock().remove(self.id, self.deadline));
+            self.registered = false;
+        }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let sleep = self.get_mut();
+        // the tick can't happen between the check and the registration
+        without_interrupts(|| {
+            let mut wheel = WHEEL.lock();
+            if time::ticks() >= sleep.deadline {
+                if sleep.registered {
+                    wheel.remove(sleep.id, sleep.deadline);
+                    sleep.registered = false;
+                }
+                Poll::Ready(())
+            } else {
+                wheel.insert(sleep.id, sleep.deadline, cx.waker());
+                sleep.registered = true;
+                Poll::Pending
+            }
+        })
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        self.unregister();
+    }
+}
+
+/// A stream that yields every period, with the tick it was due at.
+///
+/// Periods that are missed because the stream wasn't polled are skipped rather than yielded in a
+/// burst.
+pub struct Interval {
+    period: u64,
+    sleep: Sleep,
+}
+
+/// Yields every `period`, starting one period from now.
+pub fn interval(period: Duration) -> Interval {
+    let period = time::duration_to_ticks(period).max(1);
+    Interval {
+        period,
+        sleep: sleep_until(time::ticks() + period),
+    }
+}
+
+impl Stream for Interval {
+    type Item = u64;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<u64>> {
+        let interval = self.get_mut();
+        if Pin::new(&mut interval.sleep).poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+        let due = interval.sleep.deadline();
+        let missed = (time::ticks() - due) / interval.period;
+        interval.sleep.reset(due + (missed + 1) * interval.period);
+        Poll::Ready(Some(due))
+    }
+}
+
+/// Returned by `timeout` when the future didn't complete in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Elapsed;
+
+/// A future that gives up on another after some time.
+pub struct Timeout<F> {
+    future: F,
+    sleep: Sleep,
+}
+
+/// Runs `future`, unless it takes longer than `duration`.
+pub fn timeout<F: Future>(duration: Duration, future: F) -> Timeout<F> {
+    Timeout {
+        future,
+        sleep: sleep(duration),
+    }
+}
+
+impl<F: Future> Future for Timeout<F> {
+    type Output = Result<F::Output, Elapsed>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        // the future is pinned along with the timeout, it's never moved out of it
+        let timeout = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut timeout.future) };
+        if let Poll::Ready(output) = future.poll(cx) {
+            return Poll::Ready(Ok(output));
+        }
+        Pin::new(&mut timeout.sleep).poll(cx).map(|()| Err(Elapsed))
+    }
+}
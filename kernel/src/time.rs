@@ -0,0 +1,88 @@
+//! Time since boot, counted in ticks of the timer interrupt.
+//!
+//! The timer is programmed to interrupt about every millisecond, the exact period being what the
+//! hardware could be set to. Durations are converted with that period, so that the uptime doesn't
+//! drift. The PIT drives the ticks until `use_hpet` hands them to the HPET.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+use hpet::{Hpet, HpetError};
+use x86_64::instructions::interrupts::without_interrupts;
+
+pub mod hpet;
+pub mod pit;
+
+/// The rate that the timer is asked to interrupt at.
+pub const TICK_HZ: u32 = 1000;
+
+const FEMTOS_PER_NANO: u128 = 1_000_000;
+
+static TICKS: AtomicU64 = AtomicU64::new(0);
+// Femtoseconds between two ticks, as programmed.
+static TICK_PERIOD: AtomicU64 = AtomicU64::new(0);
+// The ticks and uptime when the period last changed, so that the uptime never goes back.
+static EPOCH_TICKS: AtomicU64 = AtomicU64::new(0);
+static EPOCH_NANOS: AtomicU64 = AtomicU64::new(0);
+
+/// Number of timer interrupts since `init`.
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Time between two ticks.
+pub fn tick_period() -> Duration {
+    Duration::from_nanos((TICK_PERIOD.load(Ordering::Relaxed) as u128 / FEMTOS_PER_NANO) as u64)
+}
+
+/// Time elapsed since `init`, with the resolution of a tick.
+pub fn uptime() -> Duration {
+    let epoch = Duration::from_nanos(EPOCH_NANOS.load(Ordering::Relaxed));
+    epoch + ticks_to_duration(ticks() - EPOCH_TICKS.load(Ordering::Relaxed))
+}
+
+/// How long `ticks` ticks last.
+pub fn ticks_to_duration(ticks: u64) -> Duration {
+    let femtos = ticks as u128 * TICK_PERIOD.load(Ordering::Relaxed) as u128;
+    Duration::from_nanos((femtos / FEMTOS_PER_NANO) as u64)
+}
+
+/// Number of ticks that last at least `duration`.
+pub fn duration_to_ticks(duration: Duration) -> u64 {
+    let period = TICK_PERIOD.load(Ordering::Relaxed) as u128;
+    assert!(period != 0, "timer not initialized");
+    let femtos = duration.as_nanos() * FEMTOS_PER_NANO;
+    ((femtos + period - 1) / period) as u64
+}
+
+// Called by the timer interrupt.
+pub(crate) fn tick() {
+    let now = TICKS.fetch_add(1, Ordering::Relaxed) + 1;
+    crate::task::timer::expire(now);
+}
+
+// Interrupts must be disabled.
+fn set_tick_period(period: u64) {
+    EPOCH_NANOS.store(uptime().as_nanos() as u64, Ordering::Relaxed);
+    EPOCH_TICKS.store(ticks(), Ordering::Relaxed);
+    TICK_PERIOD.store(period, Ordering::Relaxed);
+}
+
+/// Programs the PIT to interrupt at `TICK_HZ`.
+pub fn init() {
+    without_interrupts(|| set_tick_period(unsafe { pit::set_frequency(TICK_HZ) }));
+}
+
+/// Moves the ticks from the PIT to the HPET's comparator 0, which keeps the PIT's IRQ line.
+///
+/// The ticks are left to the PIT if this fails.
+pub unsafe fn use_hpet(hpet: &Hpet) -> Result<(), HpetError> {
+    if !hpet.is_periodic_capable(0) {
+        return Err(HpetError::NotPeriodic(0));
+    }
+    without_interrupts(|| {
+        hpet.enable_legacy_replacement()?;
+        let tick = Duration::from_nanos(1_000_000_000 / TICK_HZ as u64);
+        set_tick_period(hpet.set_periodic(0, tick)?);
+        Ok(())
+    })
+}
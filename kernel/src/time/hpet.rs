@@ -0,0 +1,294 @@
+//! High precision event timer, found through the ACPI HPET table.
+//!
+//! Its main counter is a monotonic clock with a resolution of tens of nanoseconds. Comparators
+//! only interrupt through the legacy replacement routes, that `time::use_hpet` enables: comparator
+//! 0 on IRQ 0 instead of the PIT, for the ticks, and comparator 1 on IRQ 8 instead of the RTC.
+
+use crate::interrupts::apic::{self, LocalApic};
+use crate::memory::mmio::MapError;
+use crate::memory::{self, Caching, Mmio};
+use acpi::{AcpiError, AcpiTables, HpetInfo};
+use conquer_once::spin::OnceCell;
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+use x86_64::PhysAddr;
+
+const CAPABILITIES: usize = 0x000;
+const CONFIGURATION: usize = 0x010;
+const MAIN_COUNTER: usize = 0x0f0;
+const REGISTERS_SIZE: usize = 0x400;
+
+// capabilities bits
+const COUNTER_64_BITS: u64 = 1 << 13;
+const LEGACY_REPLACEMENT_CAPABLE: u64 = 1 << 15;
+
+// configuration bits
+const ENABLE: u64 = 1 << 0;
+const LEGACY_REPLACEMENT: u64 = 1 << 1;
+
+// comparator configuration bits
+const INTERRUPT: u64 = 1 << 2;
+const PERIODIC: u64 = 1 << 3;
+const PERIODIC_CAPABLE: u64 = 1 << 4;
+// the next write to the comparator sets the period rather than the next match
+const SET_PERIOD: u64 = 1 << 6;
+
+const FEMTOS_PER_SECOND: u128 = 1_000_000_000_000_000;
+const FEMTOS_PER_NANO: u128 = 1_000_000;
+
+// How long calibrations count for.
+const CALIBRATION: Duration = Duration::from_millis(10);
+
+fn comparator_configuration(comparator: u8) -> usize {
+    0x100 + 0x20 * comparator as usize
+}
+
+fn comparator_value(comparator: u8) -> usize {
+    0x108 + 0x20 * comparator as usize
+}
+
+#[derive(Debug)]
+pub enum HpetError {
+    Acpi(AcpiError),
+    Map(MapError),
+    /// The main counter is 32 bits wide, it would wrap every few minutes.
+    NarrowCounter,
+    /// The comparators can't replace the PIT and RTC interrupts.
+    NoLegacyReplacement,
+    /// There's no such comparator, or it can't interrupt.
+    InvalidComparator(u8),
+    /// The comparator can't interrupt periodically.
+    NotPeriodic(u8),
+    /// `init` was already called.
+    AlreadyInitialized,
+}
+
+impl From<AcpiError> for HpetError {
+    fn from(error: AcpiError) -> Self {
+        HpetError::Acpi(error)
+    }
+}
+
+impl From<MapError> for HpetError {
+    fn from(error: MapError) -> Self {
+        HpetError::Map(error)
+    }
+}
+
+pub struct Hpet {
+    mmio: Mmio,
+    // femtoseconds between two increments of the main counter
+    period: u64,
+    comparators: u8,
+    legacy_capable: bool,
+    legacy: AtomicBool,
+}
+
+impl Hpet {
+    unsafe fn read(&self, register: usize) -> u64 {
+        self.mmio.read(register)
+    }
+
+    unsafe fn write(&self, register: usize, value: u64) {
+        self.mmio.write(register, value)
+    }
+
+    /// Femtoseconds between two increments of the main counter.
+    pub fn period(&self) -> u64 {
+        self.period
+    }
+
+    /// Increments of the main counter per second.
+    pub fn frequency(&self) -> u64 {
+        (FEMTOS_PER_SECOND / self.period as u128) as u64
+    }
+
+    pub fn comparators(&self) -> u8 {
+        self.comparators
+    }
+
+    /// The main counter, which started at 0 when the HPET was initialized.
+    pub fn counter(&self) -> u64 {
+        unsafe { self.read(MAIN_COUNTER) }
+    }
+
+    /// Time since the HPET was initialized.
+    pub fn now(&self) -> Duration {
+        self.counts_to_duration(self.counter())
+    }
+
+    pub fn counts_to_duration(&self, counts: u64) -> Duration {
+        let femtos = counts as u128 * self.period as u128;
+        Duration::from_nanos((femtos / FEMTOS_PER_NANO) as u64)
+    }
+
+    /// Number of counts that last at least `duration`, and at least one.
+    pub fn duration_to_counts(&self, duration: Duration) -> u64 {
+        let period = self.period as u128;
+        let femtos = duration.as_nanos() * FEMTOS_PER_NANO;
+        (((femtos + period - 1) / period) as u64).max(1)
+    }
+
+    /// The IRQ line that a comparator interrupts on, if it can.
+    pub fn irq(&self, comparator: u8) -> Option<u8> {
+        match comparator {
+            _ if !self.legacy.load(Ordering::Relaxed) => None,
+            0 => Some(0),
+            1 => Some(8),
+            _ => None,
+        }
+    }
+
+    pub fn is_periodic_capable(&self, comparator: u8) -> bool {
+        comparator < self.comparators
+            && unsafe { self.read(comparator_configuration(comparator)) } & PERIODIC_CAPABLE != 0
+    }
+
+    /// Interrupts once, after `delay`.
+    ///
+    /// Nothing prevents two users from programming the same comparator, they must agree on who
+    /// owns which.
+    pub unsafe fn set_one_shot(&self, comparator: u8, delay: Duration) -> Result<(), HpetError> {
+        if self.irq(comparator).is_none() {
+            return Err(HpetError::InvalidComparator(comparator));
+        }
+        let configuration = comparator_configuration(comparator);
+        self.write(
+            configuration,
+            self.read(configuration) & !(INTERRUPT | PERIODIC),
+        );
+        let deadline = self.counter() + self.duration_to_counts(delay);
+        self.write(comparator_value(comparator), deadline);
+        self.write(configuration, self.read(configuration) | INTERRUPT);
+        Ok(())
+    }
+
+    /// Interrupts every `period`, returns the period it could be set to in femtoseconds.
+    ///
+    /// The main counter keeps running, the clock and the other comparators depend on it.
+    pub unsafe fn set_periodic(&self, comparator: u8, period: Duration) -> Result<u64, HpetError> {
+        if self.irq(comparator).is_none() {
+            return Err(HpetError::InvalidComparator(comparator));
+        }
+        if !self.is_periodic_capable(comparator) {
+            return Err(HpetError::NotPeriodic(comparator));
+        }
+        let counts = self.duration_to_counts(period);
+        let configuration = comparator_configuration(comparator);
+        let value = comparator_value(comparator);
+        loop {
+            // a write only sets the next match after SET_PERIOD, which clears itself
+            self.write(
+                configuration,
+                self.read(configuration) | INTERRUPT | PERIODIC | SET_PERIOD,
+            );
+            // the first match, then the period
+            self.write(value, self.counter() + counts);
+            self.write(value, counts);
+            // a match moves the comparator ahead by the period, if the counter passed the first
+            // one before it was written, the comparator would only match once the counter wraps
+            if self.counter() < self.read(value) {
+                return Ok(counts * self.period);
+            }
+        }
+    }
+
+    /// Stops the interrupts of a comparator.
+    pub unsafe fn disable(&self, comparator: u8) {
+        if comparator < self.comparators {
+            let configuration = comparator_configuration(comparator);
+            self.write(
+                configuration,
+                self.read(configuration) & !(INTERRUPT | PERIODIC),
+            );
+        }
+    }
+
+    /// Routes comparators 0 and 1 to IRQs 0 and 8, which disconnects the PIT and the RTC.
+    pub(super) unsafe fn enable_legacy_replacement(&self) -> Result<(), HpetError> {
+        if !self.legacy_capable {
+            return Err(HpetError::NoLegacyReplacement);
+        }
+        self.write(CONFIGURATION, self.read(CONFIGURATION) | LEGACY_REPLACEMENT);
+        self.legacy.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Increments per second of another counter, measured against the main counter.
+    pub fn measure_rate(&self, mut read: impl FnMut() -> u64) -> u64 {
+        let counts = self.duration_to_counts(CALIBRATION);
+        let start = self.counter();
+        let first = read();
+        let mut now = start;
+        while now - start < counts {
+            spin_loop();
+            now = self.counter();
+        }
+        let last = read();
+        let femtos = (now - start) as u128 * self.period as u128;
+        ((last - first) as u128 * FEMTOS_PER_SECOND / femtos) as u64
+    }
+
+    /// Frequency of the time stamp counter.
+    pub fn calibrate_tsc(&self) -> u64 {
+        self.measure_rate(|| unsafe { core::arch::x86_64::_rdtsc() })
+    }
+
+    /// Frequency of the clock that drives the local APIC's timer.
+    ///
+    /// The timer mustn't be in use, it's left stopped.
+    pub unsafe fn calibrate_apic_timer(&self, local_apic: &LocalApic) -> u64 {
+        local_apic.start_timer_count();
+        let rate = self.measure_rate(|| local_apic.timer_count() as u64);
+        local_apic.stop_timer();
+        rate * apic::TIMER_DIVISOR as u64
+    }
+}
+
+static HPET: OnceCell<Hpet> = OnceCell::uninit();
+
+/// The HPET, once initialized.
+pub fn hpet() -> Option<&'static Hpet> {
+    HPET.get()
+}
+
+/// Maps the HPET that ACPI describes and starts its main counter from 0, with every comparator
+/// disabled.
+///
+/// Only the first call does, later ones fail with `AlreadyInitialized` and leave the HPET as it is.
+pub unsafe fn init(tables: &AcpiTables<crate::acpi::Handler>) -> Result<&'static Hpet, HpetError> {
+    if HPET.is_initialized() {
+        return Err(HpetError::AlreadyInitialized);
+    }
+    let info = HpetInfo::new(tables)?;
+    let mmio = memory::map_mmio(
+        PhysAddr::new(info.base_address as u64),
+        REGISTERS_SIZE,
+        Caching::Uncached,
+    )?;
+    let capabilities: u64 = mmio.read(CAPABILITIES);
+    if capabilities & COUNTER_64_BITS == 0 {
+        return Err(HpetError::NarrowCounter);
+    }
+    let hpet = Hpet {
+        mmio,
+        period: capabilities >> 32,
+        comparators: ((capabilities >> 8) & 0x1f) as u8 + 1,
+        legacy_capable: capabilities & LEGACY_REPLACEMENT_CAPABLE != 0,
+        legacy: AtomicBool::new(false),
+    };
+
+    // reset in the cell's initialization, so that a concurrent call can't reset it again
+    HPET.try_init_once(|| {
+        hpet.write(CONFIGURATION, 0);
+        for comparator in 0..hpet.comparators {
+            hpet.disable(comparator);
+        }
+        hpet.write(MAIN_COUNTER, 0);
+        hpet.write(CONFIGURATION, ENABLE);
+        hpet
+    })
+    .map_err(|_| HpetError::AlreadyInitialized)?;
+    Ok(HPET.get().unwrap())
+}
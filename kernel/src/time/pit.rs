@@ -0,0 +1,23 @@
+//! The 8253/8254 programmable interval timer, whose channel 0 is wired to IRQ 0.
+
+use x86_64::instructions::port::Port;
+
+/// Frequency of the PIT's oscillator.
+pub const OSCILLATOR_HZ: u64 = 1_193_182;
+
+const CHANNEL_0: u16 = 0x40;
+const COMMAND: u16 = 0x43;
+// channel 0, low then high byte of the reload value, rate generator, binary
+const RATE_GENERATOR: u8 = 0b00_11_010_0;
+
+/// Makes channel 0 interrupt periodically at about `hz`, returns the actual period in
+/// femtoseconds.
+pub unsafe fn set_frequency(hz: u32) -> u64 {
+    // a reload value of 0 stands for 65536
+    let divisor = (OSCILLATOR_HZ / hz as u64).max(1).min(0x10000);
+    Port::<u8>::new(COMMAND).write(RATE_GENERATOR);
+    let mut channel = Port::<u8>::new(CHANNEL_0);
+    channel.write(divisor as u8);
+    channel.write((divisor >> 8) as u8);
+    (divisor as u128 * 1_000_000_000_000_000 / OSCILLATOR_HZ as u128) as u64
+}
@@ -0,0 +1,180 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(philos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use philos::memory::address_space::{self, AddressSpace};
+use philos::memory::testing::{allocate_frame, deallocate_frame, free_frames};
+use philos::memory::{self, FRAME_ALLOCATOR};
+use x86_64::structures::paging::{Page, PageTableFlags};
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    philos::init();
+    unsafe { memory::init(boot_info) };
+    test_main();
+    philos::hlt()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    philos::test_panic_handler(info)
+}
+
+// The first page of the first level 4 entry that the kernel doesn't use.
+fn private_page() -> Page {
+    (1..512u64)
+        .map(|index| Page::containing_address(VirtAddr::new(index << 39)))
+        .find(|&page| !address_space::is_kernel_page(page))
+        .expect("the kernel uses the whole address space")
+}
+
+#[test_case]
+fn kernel_is_mapped() {
+    let space = AddressSpace::new().unwrap();
+    let value = 42u64;
+    let page = Page::containing_address(VirtAddr::from_ptr(&value));
+    assert_eq!(
+        space.translate_page(page).map(|f| f.start_address()),
+        memory::translate(page.start_address()).phys_addr()
+    );
+}
+
+#[test_case]
+fn mappings_are_private() {
+    let page = private_page();
+    let ptr = page.start_address().as_mut_ptr::<u64>();
+    let (frame_a, frame_b) = (allocate_frame(), allocate_frame());
+    let mut a = AddressSpace::new().unwrap();
+    let mut b = AddressSpace::new().unwrap();
+    unsafe {
+        a.map(page, frame_a, PageTableFlags::WRITABLE).unwrap();
+        b.map(page, frame_b, PageTableFlags::WRITABLE).unwrap();
+
+        a.activate();
+        ptr.write_volatile(1);
+        b.activate();
+        ptr.write_volatile(2);
+        a.activate();
+        assert_eq!(ptr.read_volatile(), 1);
+        address_space::activate_kernel();
+    }
+    assert_eq!(memory::translate(page.start_address()).phys_addr(), None);
+
+    assert_eq!(a.unmap(page).unwrap(), frame_a);
+    assert_eq!(b.translate_page(page), Some(frame_b));
+    // unmapped from `a` above
+    unsafe { deallocate_frame(frame_a) };
+}
+
+#[test_case]
+fn protect_changes_flags() {
+    let page = private_page();
+    let frame = allocate_frame();
+    let mut space = AddressSpace::new().unwrap();
+    unsafe {
+        space.map(page, frame, PageTableFlags::WRITABLE).unwrap();
+        space.activate();
+        space.protect(page, PageTableFlags::empty()).unwrap();
+        let writable = philos::interrupts::page_fault::probe_write(page.start_address(), 1);
+        address_space::activate_kernel();
+        assert!(!writable);
+    }
+}
+
+#[test_case]
+fn page_tables_and_frames_are_freed() {
+    let free = free_frames();
+    let page = private_page();
+    let mut space = AddressSpace::new().unwrap();
+    unsafe { space.map(page, allocate_frame(), PageTableFlags::WRITABLE) }.unwrap();
+    unsafe { space.activate() };
+    // dropping the active address space switches back to the kernel's
+    drop(space);
+    assert_eq!(free_frames(), free);
+}
+
+// An address space with `value` written in the first word of `page`.
+fn space_with(page: Page, value: u64) -> AddressSpace {
+    let mut space = AddressSpace::new().unwrap();
+    let frame = allocate_frame();
+    unsafe {
+        memory::phys_to_virt(frame.start_address())
+            .as_mut_ptr::<u64>()
+            .write_volatile(value);
+        space.map(page, frame, PageTableFlags::WRITABLE).unwrap();
+    }
+    space
+}
+
+unsafe fn read_in(space: &AddressSpace, page: Page) -> u64 {
+    space.activate();
+    let value = page.start_address().as_ptr::<u64>().read_volatile();
+    address_space::activate_kernel();
+    value
+}
+
+unsafe fn write_in(space: &AddressSpace, page: Page, value: u64) {
+    space.activate();
+    page.start_address()
+        .as_mut_ptr::<u64>()
+        .write_volatile(value);
+    address_space::activate_kernel();
+}
+
+#[test_case]
+fn forks_share_frames() {
+    let page = private_page();
+    let mut parent = space_with(page, 1);
+    let free = free_frames();
+    let child = parent.fork().unwrap();
+    let frame = parent.translate_page(page).unwrap();
+    assert_eq!(child.translate_page(page), Some(frame));
+    assert_eq!(FRAME_ALLOCATOR.get().unwrap().lock().owners(frame), 2);
+    assert_eq!(unsafe { read_in(&child, page) }, 1);
+    drop(child);
+    // only the child's page tables were allocated
+    assert_eq!(free_frames(), free);
+}
+
+#[test_case]
+fn writing_to_the_parent_copies_the_page() {
+    let page = private_page();
+    let mut parent = space_with(page, 1);
+    let child = parent.fork().unwrap();
+    unsafe {
+        write_in(&parent, page, 2);
+        assert_eq!(read_in(&parent, page), 2);
+        assert_eq!(read_in(&child, page), 1);
+    }
+    assert_ne!(parent.translate_page(page), child.translate_page(page));
+}
+
+#[test_case]
+fn writing_to_the_child_copies_the_page() {
+    let page = private_page();
+    let mut parent = space_with(page, 1);
+    let child = parent.fork().unwrap();
+    unsafe {
+        write_in(&child, page, 3);
+        assert_eq!(read_in(&child, page), 3);
+        assert_eq!(read_in(&parent, page), 1);
+    }
+}
+
+#[test_case]
+fn last_owner_writes_in_place() {
+    let page = private_page();
+    let mut parent = space_with(page, 1);
+    let frame = parent.translate_page(page).unwrap();
+    drop(parent.fork().unwrap());
+    let free = free_frames();
+    unsafe { write_in(&parent, page, 4) };
+    assert_eq!(parent.translate_page(page), Some(frame));
+    assert_eq!(free_frames(), free);
+}
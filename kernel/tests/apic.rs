@@ -0,0 +1,39 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(philos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use philos::interrupts::apic;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    philos::init();
+    unsafe { philos::memory::init(boot_info) };
+    philos::allocator::init().expect("heap initialization failed");
+    let acpi = unsafe { philos::acpi::init() }.expect("unable to enable ACPI");
+    unsafe { apic::init(&acpi) }.expect("failed to initialize the APICs");
+    test_main();
+    philos::hlt()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    philos::test_panic_handler(info)
+}
+
+#[test_case]
+fn local_apic_is_used() {
+    assert!(apic::local_apic().is_some());
+}
+
+// Hangs if the timer isn't routed through the I/O APIC or isn't acknowledged.
+#[test_case]
+fn timer_interrupts_arrive() {
+    for _ in 0..3 {
+        x86_64::instructions::hlt();
+    }
+}
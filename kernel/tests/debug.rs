@@ -0,0 +1,26 @@
+#![no_std]
+#![no_main]
+#![feature(asm)]
+
+use core::panic::PanicInfo;
+use philos::{qemu, serial_print, serial_println};
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    serial_print!("debug::single_step...\t");
+    philos::init();
+
+    // traps after the instruction following the one that sets the trap flag
+    unsafe {
+        asm!("pushfq", "or qword ptr [rsp], 0x100", "popfq", "nop",);
+    }
+
+    serial_println!("[no exception]");
+    qemu::exit(qemu::ExitCode::Failure);
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    philos::expected_panic_handler(info, "EXCEPTION: Debug (#DB, vector 1)\nerror code: none")
+}
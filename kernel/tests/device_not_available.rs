@@ -0,0 +1,36 @@
+#![no_std]
+#![no_main]
+#![feature(asm)]
+
+use core::panic::PanicInfo;
+use philos::{qemu, serial_print, serial_println};
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    serial_print!("device_not_available::task_switched...\t");
+    philos::init();
+
+    // a task switch makes the next x87 instruction fault, so that its state can be saved
+    unsafe {
+        asm!(
+            "mov {0}, cr0",
+            "or {0}, 0x8",
+            "mov cr0, {0}",
+            "fninit",
+            out(reg) _,
+            options(nomem, nostack),
+        );
+    }
+
+    serial_println!("[no exception]");
+    qemu::exit(qemu::ExitCode::Failure);
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    philos::expected_panic_handler(
+        info,
+        "EXCEPTION: Device not available (#NM, vector 7)\nerror code: none",
+    )
+}
@@ -0,0 +1,31 @@
+#![no_std]
+#![no_main]
+#![feature(asm)]
+
+use core::panic::PanicInfo;
+use philos::{qemu, serial_print, serial_println};
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    serial_print!("divide_error::divide_by_zero...\t");
+    philos::init();
+
+    unsafe {
+        asm!(
+            "div {}",
+            in(reg) 0u64,
+            inout("rax") 1u64 => _,
+            inout("rdx") 0u64 => _,
+            options(nomem, nostack),
+        );
+    }
+
+    serial_println!("[no exception]");
+    qemu::exit(qemu::ExitCode::Failure);
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    philos::expected_panic_handler(info, "EXCEPTION: Divide error (#DE, vector 0)")
+}
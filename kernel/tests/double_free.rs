@@ -0,0 +1,44 @@
+#![no_std]
+#![no_main]
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::panic::PanicInfo;
+use philos::allocator::debug::DebugAllocator;
+use philos::allocator::free_list::FreeListAllocator;
+use philos::allocator::Locked;
+use philos::{qemu, serial_print, serial_println};
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    should_fail();
+    serial_println!("[test did not panic]");
+    qemu::exit(qemu::ExitCode::Failure);
+    loop {}
+}
+
+const ARENA_SIZE: usize = 4096;
+
+#[repr(align(4096))]
+struct Arena([u8; ARENA_SIZE]);
+
+static mut ARENA: Arena = Arena([0; ARENA_SIZE]);
+
+fn should_fail() {
+    serial_print!("double_free::should_fail...\t");
+    let allocator = DebugAllocator::new(Locked::new(FreeListAllocator::new()));
+    let layout = Layout::from_size_align(32, 8).unwrap();
+    unsafe {
+        allocator
+            .inner()
+            .lock()
+            .init(ARENA.0.as_ptr() as usize, ARENA_SIZE);
+        let ptr = allocator.alloc(layout);
+        allocator.dealloc(ptr, layout);
+        allocator.dealloc(ptr, layout);
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    philos::expected_panic_handler(info, "double free of")
+}
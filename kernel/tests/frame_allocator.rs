@@ -0,0 +1,175 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(philos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use philos::memory::frame::MAX_ORDER;
+use philos::memory::{self, FRAME_ALLOCATOR};
+use x86_64::structures::paging::{
+    FrameAllocator, FrameDeallocator, PageSize, PhysFrame, Size1GiB, Size2MiB,
+};
+use x86_64::PhysAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    philos::init();
+    unsafe { memory::init(boot_info) };
+    test_main();
+    philos::hlt()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    philos::test_panic_handler(info)
+}
+
+// There's no heap in this test, so allocated frames are chained together through their first word.
+fn allocate_all() -> (Option<PhysFrame>, usize) {
+    let mut allocator = FRAME_ALLOCATOR.get().unwrap().lock();
+    let mut head: Option<PhysFrame> = None;
+    let mut count = 0;
+    while let Some(frame) = allocator.allocate_frame() {
+        let next = head.map(|f| f.start_address().as_u64()).unwrap_or(0);
+        unsafe { *memory::phys_to_virt(frame.start_address()).as_mut_ptr::<u64>() = next };
+        head = Some(frame);
+        count += 1;
+    }
+    (head, count)
+}
+
+fn free_all(mut head: Option<PhysFrame>) {
+    let mut allocator = FRAME_ALLOCATOR.get().unwrap().lock();
+    while let Some(frame) = head {
+        let next = unsafe { *memory::phys_to_virt(frame.start_address()).as_ptr::<u64>() };
+        unsafe { allocator.deallocate_frame(frame) };
+        head = match next {
+            0 => None,
+            addr => Some(PhysFrame::containing_address(PhysAddr::new(addr))),
+        };
+    }
+}
+
+fn free_frames() -> usize {
+    FRAME_ALLOCATOR.get().unwrap().lock().free_frames()
+}
+
+#[test_case]
+fn allocate_free_and_reuse_every_frame() {
+    let available = free_frames();
+
+    let (head, count) = allocate_all();
+    assert_eq!(count, available);
+    assert_eq!(free_frames(), 0);
+    free_all(head);
+    assert_eq!(free_frames(), available);
+
+    let (head, count) = allocate_all();
+    assert_eq!(count, available);
+    free_all(head);
+    assert_eq!(free_frames(), available);
+}
+
+#[test_case]
+fn freed_frame_is_reused() {
+    let mut allocator = FRAME_ALLOCATOR.get().unwrap().lock();
+    let frame: PhysFrame = allocator.allocate_frame().unwrap();
+    unsafe { allocator.deallocate_frame(frame) };
+    assert_eq!(allocator.allocate_frame(), Some(frame));
+    unsafe { allocator.deallocate_frame(frame) };
+}
+
+#[test_case]
+fn contiguous_allocation() {
+    let mut allocator = FRAME_ALLOCATOR.get().unwrap().lock();
+    let available = allocator.free_frames();
+    let range = allocator.allocate_contiguous(16).unwrap();
+    assert_eq!(range.end - range.start, 16);
+    assert_eq!(allocator.free_frames(), available - 16);
+    unsafe { allocator.deallocate_contiguous(range) };
+    assert_eq!(allocator.free_frames(), available);
+
+    let range = allocator.allocate_contiguous(5).unwrap();
+    assert_eq!(range.end - range.start, 5);
+    assert_eq!(allocator.free_frames(), available - 5);
+    unsafe { allocator.deallocate_contiguous(range) };
+    assert_eq!(allocator.free_frames(), available);
+}
+
+#[test_case]
+fn freed_blocks_merge_with_their_buddies() {
+    let mut allocator = FRAME_ALLOCATOR.get().unwrap().lock();
+    let before = allocator.stats();
+    let frames: [PhysFrame; 3] = [
+        allocator.allocate_frame().unwrap(),
+        allocator.allocate_frame().unwrap(),
+        allocator.allocate_frame().unwrap(),
+    ];
+    assert_ne!(allocator.stats(), before);
+    for frame in frames.iter() {
+        unsafe { allocator.deallocate_frame(*frame) };
+    }
+    assert_eq!(allocator.stats(), before);
+}
+
+#[test_case]
+fn huge_frames() {
+    let mut allocator = FRAME_ALLOCATOR.get().unwrap().lock();
+    let available = allocator.free_frames();
+
+    let frame: PhysFrame<Size2MiB> = allocator.allocate_frame().unwrap();
+    assert!(frame.start_address().is_aligned(Size2MiB::SIZE));
+    assert_eq!(allocator.free_frames(), available - 512);
+    unsafe { allocator.deallocate_frame(frame) };
+    assert_eq!(allocator.free_frames(), available);
+
+    // qemu only has 128MiB of memory by default
+    let has_1gib_block = allocator.stats().largest_free_order() == Some(MAX_ORDER);
+    let frame: Option<PhysFrame<Size1GiB>> = allocator.allocate_frame();
+    assert_eq!(frame.is_some(), has_1gib_block);
+    if let Some(frame) = frame {
+        assert!(frame.start_address().is_aligned(Size1GiB::SIZE));
+        unsafe { allocator.deallocate_frame(frame) };
+    }
+    assert_eq!(allocator.free_frames(), available);
+}
+
+#[test_case]
+fn fragmentation() {
+    let mut allocator = FRAME_ALLOCATOR.get().unwrap().lock();
+    let before = allocator.stats();
+    let range = allocator.allocate_contiguous(1024).unwrap();
+    // free every other frame so none of them can merge with their buddy
+    for frame in range.step_by(2) {
+        unsafe { allocator.deallocate_frame(frame) };
+    }
+    let fragmented = allocator.stats();
+    assert_eq!(fragmented.free_blocks[0], before.free_blocks[0] + 512);
+    assert!(fragmented.fragmentation() >= before.fragmentation());
+
+    for frame in range.skip(1).step_by(2) {
+        unsafe { allocator.deallocate_frame(frame) };
+    }
+    assert_eq!(allocator.stats(), before);
+}
+
+#[test_case]
+fn shared_frames_are_freed_with_their_last_owner() {
+    let mut allocator = FRAME_ALLOCATOR.get().unwrap().lock();
+    let free = allocator.free_frames();
+    let frame: PhysFrame = allocator.allocate_frame().unwrap();
+    allocator.share(frame);
+    allocator.share(frame);
+    assert_eq!(allocator.owners(frame), 3);
+    unsafe {
+        allocator.deallocate_frame(frame);
+        allocator.deallocate_frame(frame);
+    }
+    assert_eq!(allocator.owners(frame), 1);
+    assert_eq!(allocator.free_frames(), free - 1);
+    unsafe { allocator.deallocate_frame(frame) };
+    assert_eq!(allocator.free_frames(), free);
+}
@@ -0,0 +1,30 @@
+#![no_std]
+#![no_main]
+#![feature(asm)]
+
+use core::panic::PanicInfo;
+use philos::{qemu, serial_print, serial_println};
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    serial_print!("general_protection::selector_past_the_gdt...\t");
+    philos::init();
+
+    // the GDT only has a few entries
+    unsafe {
+        asm!(
+            "mov ds, {:x}",
+            in(reg) 0x50u16,
+            options(nostack),
+        );
+    }
+
+    serial_println!("[no exception]");
+    qemu::exit(qemu::ExitCode::Failure);
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    philos::expected_panic_handler(info, "error code: 0x50: GDT index 10")
+}
@@ -0,0 +1,154 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(philos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::vec::Vec;
+use bootloader::{entry_point, BootInfo};
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    philos::init();
+    unsafe { philos::memory::init(boot_info) };
+    philos::allocator::init().expect("heap initialization failed");
+    test_main();
+    philos::hlt()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    philos::test_panic_handler(info)
+}
+
+#[test_case]
+fn simple_allocation() {
+    let heap_value_1 = Box::new(41);
+    let heap_value_2 = Box::new(13);
+    assert_eq!(*heap_value_1, 41);
+    assert_eq!(*heap_value_2, 13);
+}
+
+#[test_case]
+fn large_vec() {
+    let n = 1000;
+    let mut vec = Vec::new();
+    for i in 0..n {
+        vec.push(i);
+    }
+    assert_eq!(vec.iter().sum::<u64>(), (n - 1) * n / 2);
+}
+
+#[test_case]
+fn many_boxes() {
+    for i in 0..philos::allocator::HEAP_SIZE {
+        let x = Box::new(i);
+        assert_eq!(*x, i);
+    }
+}
+
+#[test_case]
+fn allocation_beyond_initial_heap() {
+    let size = 4 * philos::allocator::HEAP_SIZE;
+    let mut vec: Vec<u8> = Vec::with_capacity(size);
+    vec.resize(size, 1);
+    assert_eq!(vec.iter().map(|&b| b as usize).sum::<usize>(), size);
+    assert!(philos::allocator::heap_size() > size);
+}
+
+#[test_case]
+fn many_allocations_beyond_initial_heap() {
+    let n = philos::allocator::HEAP_SIZE / 64;
+    let boxes: Vec<Box<[u8; 128]>> = (0..n).map(|_| Box::new([0xa5; 128])).collect();
+    assert!(boxes.iter().all(|b| b.iter().all(|&x| x == 0xa5)));
+}
+
+#[test_case]
+fn stats_track_allocations() {
+    let before = philos::allocator::stats();
+    let value = Box::new([0u8; 100]);
+    let during = philos::allocator::stats();
+    assert_eq!(during.bytes_in_use, before.bytes_in_use + 100);
+    assert_eq!(during.allocations, before.allocations + 1);
+    assert!(during.peak_bytes_in_use >= during.bytes_in_use);
+
+    drop(value);
+    let after = philos::allocator::stats();
+    assert_eq!(after.bytes_in_use, before.bytes_in_use);
+    assert_eq!(after.live_allocations(), before.live_allocations());
+    assert_eq!(after.peak_bytes_in_use, during.peak_bytes_in_use);
+}
+
+// Keeps what's written to it, without allocating.
+struct Buffer {
+    bytes: [u8; 4096],
+    len: usize,
+}
+
+impl Buffer {
+    fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap()
+    }
+}
+
+impl Write for Buffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end > self.bytes.len() {
+            return Err(fmt::Error);
+        }
+        self.bytes[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "alloc-bump")]
+const HEADER: &str = "bump heap ";
+#[cfg(feature = "alloc-fixed")]
+const HEADER: &str = "fixed heap ";
+#[cfg(feature = "alloc-free-list")]
+const HEADER: &str = "free list heap ";
+#[cfg(feature = "alloc-linked-list")]
+const HEADER: &str = "linked list heap ";
+
+#[test_case]
+fn heap_map() {
+    let value = Box::new([0u8; 100]);
+    let stats = philos::allocator::stats();
+    let mut buffer = Buffer {
+        bytes: [0; 4096],
+        len: 0,
+    };
+    philos::allocator::write_heap(&mut buffer).unwrap();
+    drop(value);
+
+    let expected = format!(
+        "heap: {} bytes in use (peak {}), {} live allocations, {} allocations, {} failed",
+        stats.bytes_in_use,
+        stats.peak_bytes_in_use,
+        stats.live_allocations(),
+        stats.allocations,
+        stats.failed_allocations
+    );
+    let mut lines = buffer.as_str().lines();
+    assert_eq!(lines.next(), Some(expected.as_str()));
+    #[cfg(feature = "alloc-debug")]
+    assert!(lines.next().unwrap().starts_with("debug allocator: "));
+    assert!(lines.next().unwrap().starts_with(HEADER));
+
+    // the debug allocator's overhead moves the value to a larger class
+    #[cfg(all(feature = "alloc-fixed", not(feature = "alloc-debug")))]
+    assert!(lines
+        .any(|line| line.trim_start().starts_with("128 bytes blocks: ")
+            && !line.contains(": 0 in use")));
+    #[cfg(feature = "alloc-free-list")]
+    assert!(lines.any(|line| line.contains(" used (")));
+}
@@ -0,0 +1,145 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(philos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use core::time::Duration;
+use philos::interrupts::{apic, register_irq, unregister_irq};
+use philos::time::hpet::{self, Hpet, HpetError};
+use philos::{serial_print, time};
+
+static INITIALIZED_AGAIN: AtomicBool = AtomicBool::new(false);
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    philos::init();
+    unsafe { philos::memory::init(boot_info) };
+    philos::allocator::init().expect("heap initialization failed");
+    let acpi = unsafe { philos::acpi::init() }.expect("unable to enable ACPI");
+    unsafe { apic::init(&acpi) }.expect("failed to initialize the APICs");
+    let hpet = unsafe { hpet::init(&acpi) }.expect("failed to initialize the HPET");
+    unsafe { time::use_hpet(hpet) }.expect("failed to tick with the HPET");
+    let again = unsafe { hpet::init(&acpi) };
+    INITIALIZED_AGAIN.store(
+        !matches!(again, Err(HpetError::AlreadyInitialized)),
+        Ordering::SeqCst,
+    );
+    test_main();
+    philos::hlt()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    philos::test_panic_handler(info)
+}
+
+fn hpet() -> &'static Hpet {
+    hpet::hpet().unwrap()
+}
+
+#[test_case]
+fn clock_is_monotonic() {
+    let hpet = hpet();
+    let mut last = hpet.now();
+    for _ in 0..100 {
+        let now = hpet.now();
+        assert!(now >= last);
+        last = now;
+    }
+    x86_64::instructions::hlt();
+    assert!(hpet.now() > last);
+}
+
+#[test_case]
+fn ticks_follow_the_clock() {
+    let hpet = hpet();
+    let start = hpet.now();
+    let ticks = time::ticks();
+    while time::ticks() - ticks < 20 {
+        x86_64::instructions::hlt();
+    }
+    let elapsed = hpet.now() - start;
+    // the first tick may have come right after `ticks` was read
+    assert!(elapsed >= time::ticks_to_duration(19));
+    assert!(elapsed <= time::ticks_to_duration(21));
+}
+
+static INTERRUPTS: AtomicUsize = AtomicUsize::new(0);
+
+fn count_interrupt() {
+    INTERRUPTS.fetch_add(1, Ordering::SeqCst);
+}
+
+#[test_case]
+fn one_shot_comparator_interrupts_once() {
+    let hpet = hpet();
+    let handle = register_irq(hpet.irq(1).unwrap(), count_interrupt).unwrap();
+    INTERRUPTS.store(0, Ordering::SeqCst);
+    let start = hpet.now();
+    unsafe { hpet.set_one_shot(1, Duration::from_millis(2)) }.unwrap();
+    while INTERRUPTS.load(Ordering::SeqCst) == 0 {
+        x86_64::instructions::hlt();
+    }
+    assert!(hpet.now() - start >= Duration::from_millis(2));
+
+    let end = hpet.now() + Duration::from_millis(5);
+    while hpet.now() < end {
+        x86_64::instructions::hlt();
+    }
+    assert_eq!(INTERRUPTS.load(Ordering::SeqCst), 1);
+    unsafe { hpet.disable(1) };
+    unregister_irq(handle);
+}
+
+#[test_case]
+fn periodic_comparator_interrupts_repeatedly() {
+    let hpet = hpet();
+    if !hpet.is_periodic_capable(1) {
+        assert!(matches!(
+            unsafe { hpet.set_periodic(1, Duration::from_millis(1)) },
+            Err(HpetError::NotPeriodic(1))
+        ));
+        serial_print!("[skipped: comparator 1 isn't periodic capable] ");
+        return;
+    }
+    let handle = register_irq(hpet.irq(1).unwrap(), count_interrupt).unwrap();
+    INTERRUPTS.store(0, Ordering::SeqCst);
+    unsafe { hpet.set_periodic(1, Duration::from_millis(1)) }.unwrap();
+    while INTERRUPTS.load(Ordering::SeqCst) < 3 {
+        x86_64::instructions::hlt();
+    }
+    unsafe { hpet.disable(1) };
+    unregister_irq(handle);
+}
+
+#[test_case]
+fn initializing_again_fails() {
+    assert!(!INITIALIZED_AGAIN.load(Ordering::SeqCst));
+    // the ticks still come from comparator 0
+    let ticks = time::ticks();
+    x86_64::instructions::hlt();
+    assert!(time::ticks() > ticks);
+}
+
+#[test_case]
+fn comparators_without_a_route_are_rejected() {
+    let hpet = hpet();
+    assert!(matches!(
+        unsafe { hpet.set_one_shot(2, Duration::from_millis(1)) },
+        Err(HpetError::InvalidComparator(2))
+    ));
+}
+
+#[test_case]
+fn calibrations_are_plausible() {
+    let hpet = hpet();
+    // QEMU's TSC runs at the host's frequency
+    assert!(hpet.calibrate_tsc() > 100_000_000);
+    let local_apic = apic::local_apic().unwrap();
+    assert!(unsafe { hpet.calibrate_apic_timer(local_apic) } > 1_000_000);
+}
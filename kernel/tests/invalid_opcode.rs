@@ -0,0 +1,23 @@
+#![no_std]
+#![no_main]
+#![feature(asm)]
+
+use core::panic::PanicInfo;
+use philos::{qemu, serial_print, serial_println};
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    serial_print!("invalid_opcode::ud2...\t");
+    philos::init();
+
+    unsafe { asm!("ud2", options(nomem, nostack)) };
+
+    serial_println!("[no exception]");
+    qemu::exit(qemu::ExitCode::Failure);
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    philos::expected_panic_handler(info, "EXCEPTION: Invalid opcode (#UD, vector 6)")
+}
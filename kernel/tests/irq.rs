@@ -0,0 +1,94 @@
+#![no_std]
+#![no_main]
+#![feature(asm)]
+#![feature(custom_test_frameworks)]
+#![test_runner(philos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use philos::interrupts::irq::{self, IRQ_LINES, MAX_SHARED};
+use philos::interrupts::{register_irq, unregister_irq, IrqError};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    philos::init();
+    unsafe { philos::memory::init(boot_info) };
+    test_main();
+    philos::hlt()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    philos::test_panic_handler(info)
+}
+
+// A line that no device uses, delivered on vector 0x25.
+const IRQ: u8 = 5;
+
+fn raise() {
+    unsafe { asm!("int 0x25") };
+}
+
+static FIRST: AtomicUsize = AtomicUsize::new(0);
+static SECOND: AtomicUsize = AtomicUsize::new(0);
+
+fn first() {
+    FIRST.fetch_add(1, Ordering::SeqCst);
+}
+
+fn second() {
+    SECOND.fetch_add(1, Ordering::SeqCst);
+}
+
+#[test_case]
+fn shared_handlers_are_all_called() {
+    assert_eq!(irq::vector(IRQ), 0x25);
+    let a = register_irq(IRQ, first).unwrap();
+    let b = register_irq(IRQ, second).unwrap();
+    raise();
+    assert_eq!(FIRST.load(Ordering::SeqCst), 1);
+    assert_eq!(SECOND.load(Ordering::SeqCst), 1);
+
+    unregister_irq(a);
+    raise();
+    assert_eq!(FIRST.load(Ordering::SeqCst), 1);
+    assert_eq!(SECOND.load(Ordering::SeqCst), 2);
+
+    unregister_irq(b);
+    raise();
+    assert_eq!(SECOND.load(Ordering::SeqCst), 2);
+    assert_eq!(irq::handlers(IRQ), 0);
+}
+
+#[test_case]
+fn lines_have_limited_handlers() {
+    let mut handles = [None; MAX_SHARED];
+    for handle in handles.iter_mut() {
+        *handle = Some(register_irq(IRQ, first).unwrap());
+    }
+    assert_eq!(register_irq(IRQ, first), Err(IrqError::TooManyHandlers));
+    for &handle in handles.iter().flatten() {
+        unregister_irq(handle);
+    }
+}
+
+#[test_case]
+fn invalid_lines_are_rejected() {
+    assert_eq!(
+        register_irq(IRQ_LINES as u8, first),
+        Err(IrqError::InvalidIrq)
+    );
+    assert_eq!(irq::handlers(IRQ_LINES as u8), 0);
+    assert_eq!(irq::handlers(u8::MAX), 0);
+}
+
+// Hangs if the timer's line isn't enabled or acknowledged.
+#[test_case]
+fn timer_interrupts_arrive() {
+    for _ in 0..3 {
+        x86_64::instructions::hlt();
+    }
+}
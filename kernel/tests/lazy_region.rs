@@ -0,0 +1,117 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(philos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use philos::memory::{self, LazyRegion, FRAME_ALLOCATOR};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    philos::init();
+    unsafe { memory::init(boot_info) };
+    test_main();
+    philos::hlt()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    philos::test_panic_handler(info)
+}
+
+const MIB: usize = 1024 * 1024;
+
+fn free_frames() -> usize {
+    FRAME_ALLOCATOR.get().unwrap().lock().free_frames()
+}
+
+fn touch(region: &LazyRegion, offset: usize, value: u8) {
+    let ptr = (region.start() + offset).as_mut_ptr::<u8>();
+    unsafe { ptr.write_volatile(value) };
+}
+
+fn read(region: &LazyRegion, offset: usize) -> u8 {
+    let ptr = (region.start() + offset).as_ptr::<u8>();
+    unsafe { ptr.read_volatile() }
+}
+
+#[test_case]
+fn reserving_uses_no_frames() {
+    let frames = free_frames();
+    let region = LazyRegion::new(64 * MIB).unwrap();
+    assert_eq!(region.len(), 64 * MIB);
+    assert_eq!(region.resident_pages(), 0);
+    assert_eq!(free_frames(), frames);
+}
+
+#[test_case]
+fn sparse_pages_are_backed_on_first_touch() {
+    let region = LazyRegion::new(64 * MIB).unwrap();
+    let frames = free_frames();
+    for i in 0..16 {
+        touch(&region, i * 4 * MIB + 123, i as u8);
+    }
+    assert_eq!(region.resident_pages(), 16);
+    // each page may need its own page table
+    let used = frames - free_frames();
+    assert!(used >= 16 && used <= 16 * 2 + 2, "{} frames used", used);
+
+    // touching them again doesn't use more
+    let frames = free_frames();
+    for i in 0..16 {
+        assert_eq!(read(&region, i * 4 * MIB + 123), i as u8);
+        touch(&region, i * 4 * MIB, 1);
+    }
+    assert_eq!(free_frames(), frames);
+
+    // dropping gives the pages' frames back, page tables are kept
+    drop(region);
+    assert_eq!(free_frames(), frames + 16);
+}
+
+#[test_case]
+fn neighbouring_pages_use_one_frame_each() {
+    let region = LazyRegion::new(MIB).unwrap();
+    // eight pages under the same page table, a region of less than 2 MiB crosses at most one
+    let start = region.start();
+    let first = if u16::from(start.p1_index()) <= 512 - 8 {
+        start
+    } else {
+        start.align_up(2 * MIB as u64)
+    };
+    let offset = (first - start) as usize;
+    // the first touch may allocate page tables
+    touch(&region, offset, 1);
+    let frames = free_frames();
+    for page in 1..8 {
+        touch(&region, offset + page * 4096 + 42, 1);
+    }
+    assert_eq!(frames - free_frames(), 7);
+    assert_eq!(region.resident_pages(), 8);
+}
+
+#[test_case]
+fn pages_start_zeroed() {
+    let region = LazyRegion::new(8 * 4096).unwrap();
+    for offset in (0..region.len()).step_by(1000) {
+        assert_eq!(read(&region, offset), 0);
+    }
+    // reading is enough to back a page
+    assert_eq!(region.resident_pages(), 8);
+}
+
+#[test_case]
+fn dropped_regions_are_recycled() {
+    let region = LazyRegion::new(16 * MIB).unwrap();
+    touch(&region, 0, 0xa5);
+    let start = region.start();
+    drop(region);
+
+    let region = LazyRegion::new(16 * MIB).unwrap();
+    assert_eq!(region.start(), start);
+    assert_eq!(region.resident_pages(), 0);
+    assert_eq!(read(&region, 0), 0);
+}
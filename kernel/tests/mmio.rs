@@ -0,0 +1,99 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(philos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use philos::memory::{self, Caching, FRAME_ALLOCATOR};
+use x86_64::structures::paging::frame::PhysFrameRange;
+use x86_64::PhysAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    philos::init();
+    unsafe { memory::init(boot_info) };
+    test_main();
+    philos::hlt()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    philos::test_panic_handler(info)
+}
+
+// The frames are also mapped write-back by the physical memory mapping, so only write-back
+// mappings are accessed.
+fn allocate_frames(count: usize) -> PhysFrameRange {
+    FRAME_ALLOCATOR
+        .get()
+        .unwrap()
+        .lock()
+        .allocate_contiguous(count)
+        .expect("no free frames")
+}
+
+fn deallocate_frames(frames: PhysFrameRange) {
+    unsafe {
+        FRAME_ALLOCATOR
+            .get()
+            .unwrap()
+            .lock()
+            .deallocate_contiguous(frames)
+    };
+}
+
+#[test_case]
+fn mapping_points_at_the_requested_address() {
+    let frames = allocate_frames(1);
+    let phys = frames.start.start_address() + 158u64;
+    for &caching in [
+        Caching::WriteBack,
+        Caching::WriteThrough,
+        Caching::WriteCombining,
+        Caching::Uncached,
+    ]
+    .iter()
+    {
+        let mmio = unsafe { memory::map_mmio(phys, 2, caching) }.unwrap();
+        assert_eq!(mmio.start().as_u64() % 4096, 158);
+        assert_eq!(memory::translate(mmio.start()).phys_addr(), Some(phys));
+    }
+
+    let mmio = unsafe { memory::map_mmio(phys, 2, Caching::WriteBack) }.unwrap();
+    unsafe { mmio.write::<u16>(0, 0x0f21) };
+    drop(mmio);
+    let identity = memory::phys_to_virt(phys).as_ptr::<u16>();
+    assert_eq!(unsafe { identity.read_volatile() }, 0x0f21);
+    deallocate_frames(frames);
+}
+
+#[test_case]
+fn mapping_spans_pages() {
+    let frames = allocate_frames(2);
+    let phys = frames.start.start_address() + 4095u64;
+    let mmio = unsafe { memory::map_mmio(phys, 2, Caching::WriteBack) }.unwrap();
+    unsafe { mmio.write::<u16>(0, 0x0f21) };
+    assert_eq!(unsafe { mmio.read::<u16>(0) }, 0x0f21);
+    assert_eq!(
+        memory::translate(mmio.start() + 1u64).phys_addr(),
+        Some(phys + 1u64)
+    );
+    drop(mmio);
+    deallocate_frames(frames);
+}
+
+#[test_case]
+fn dropped_mappings_are_recycled() {
+    let frames = allocate_frames(1);
+    let phys = frames.start.start_address();
+    let first = unsafe { memory::map_mmio(phys, 4096, Caching::WriteBack) }.unwrap();
+    let start = first.start();
+    drop(first);
+    let second = unsafe { memory::map_mmio(phys, 4096, Caching::WriteBack) }.unwrap();
+    assert_eq!(second.start(), start);
+    drop(second);
+    deallocate_frames(frames);
+}
@@ -0,0 +1,69 @@
+#![no_std]
+#![no_main]
+#![feature(abi_x86_interrupt)]
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicU64, Ordering};
+use lazy_static::lazy_static;
+use philos::memory;
+use philos::{qemu, serial_print, serial_println};
+use x86_64::registers::control::Cr2;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
+
+lazy_static! {
+    static ref TEST_IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        idt.page_fault.set_handler_fn(page_fault_handler);
+        idt
+    };
+}
+
+// Address of the code written to the heap.
+static CODE: AtomicU64 = AtomicU64::new(0);
+
+extern "x86-interrupt" fn page_fault_handler(
+    sf: &mut InterruptStackFrame,
+    error_code: PageFaultErrorCode,
+) {
+    let code = CODE.load(Ordering::SeqCst);
+    let expected = PageFaultErrorCode::PROTECTION_VIOLATION | PageFaultErrorCode::INSTRUCTION_FETCH;
+    if Cr2::read().as_u64() == code && error_code.contains(expected) {
+        serial_println!("[ok]");
+        qemu::exit(qemu::ExitCode::Success);
+        philos::hlt();
+    }
+    panic!(
+        "unexpected page fault at {:?} ({:?})\n{:#?}",
+        Cr2::read(),
+        error_code,
+        sf
+    );
+}
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    serial_print!("no_execute::heap_is_not_executable...\t");
+
+    philos::gdt::init_gdt();
+    unsafe { memory::init(boot_info) };
+    philos::allocator::init().expect("heap initialization failed");
+    TEST_IDT.load();
+
+    // ret
+    let code = Box::new([0xc3u8; 16]);
+    CODE.store(code.as_ptr() as u64, Ordering::SeqCst);
+    let function: extern "C" fn() = unsafe { core::mem::transmute(code.as_ptr()) };
+    function();
+
+    panic!("Execution continued in heap memory");
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    philos::test_panic_handler(info);
+}
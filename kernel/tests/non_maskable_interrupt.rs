@@ -0,0 +1,27 @@
+#![no_std]
+#![no_main]
+#![feature(asm)]
+
+use core::panic::PanicInfo;
+use philos::{qemu, serial_print, serial_println};
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    serial_print!("non_maskable_interrupt::report_has_the_registers...\t");
+    philos::init();
+
+    // runs the NMI handler like a software interrupt
+    unsafe {
+        asm!("int 2", in("r12") 0xfeed_faceu64, options(nomem, nostack));
+    }
+
+    serial_println!("[no exception]");
+    qemu::exit(qemu::ExitCode::Failure);
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    // the value R12 had when the interrupt happened
+    philos::expected_panic_handler(info, "R12: 0x00000000feedface")
+}
@@ -0,0 +1,133 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(philos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use philos::interrupts::page_fault::{probe_read, probe_write};
+use philos::memory::lazy::{self, Backing};
+use philos::memory::vmm::{self, VMM};
+use philos::memory::{self, FRAME_ALLOCATOR};
+use x86_64::structures::paging::page::PageRange;
+use x86_64::structures::paging::PageTableFlags;
+use x86_64::{PhysAddr, VirtAddr};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    philos::init();
+    unsafe { memory::init(boot_info) };
+    test_main();
+    philos::hlt()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    philos::test_panic_handler(info)
+}
+
+fn free_frames() -> usize {
+    FRAME_ALLOCATOR.get().unwrap().lock().free_frames()
+}
+
+// 16 pages that share a page table, so that only the first access allocates page tables.
+fn pages() -> PageRange {
+    VMM.lock()
+        .allocate_aligned(vmm::LAZY, 16, 16 * 4096)
+        .unwrap()
+}
+
+fn page_addr(pages: PageRange, n: u64) -> VirtAddr {
+    (pages.start + n).start_address()
+}
+
+#[test_case]
+fn probing_unmapped_memory() {
+    let pages = pages();
+    assert_eq!(probe_read(page_addr(pages, 0)), None);
+    assert!(!probe_write(page_addr(pages, 1) + 8u64, 1));
+}
+
+#[test_case]
+fn probing_mapped_memory() {
+    let mut value = 42u8;
+    let addr = VirtAddr::from_ptr(&mut value as *mut u8);
+    assert_eq!(probe_read(addr), Some(42));
+    assert!(probe_write(addr, 7));
+    assert_eq!(unsafe { core::ptr::read_volatile(&value) }, 7);
+}
+
+#[test_case]
+fn demand_zero_pages_are_mapped_on_access() {
+    let pages = pages();
+    unsafe { lazy::map_lazy(pages, Backing::Zero, PageTableFlags::WRITABLE) }.unwrap();
+
+    unsafe { page_addr(pages, 0).as_mut_ptr::<u64>().write_volatile(1) };
+    let frames = free_frames();
+    unsafe {
+        page_addr(pages, 3).as_mut_ptr::<u64>().write_volatile(3);
+        page_addr(pages, 10).as_mut_ptr::<u64>().write_volatile(10);
+    }
+    assert_eq!(probe_read(page_addr(pages, 12) + 100u64), Some(0));
+    assert_eq!(frames - free_frames(), 3);
+    assert_eq!(lazy::mapped_pages(pages), 4);
+    assert_eq!(
+        unsafe { page_addr(pages, 3).as_ptr::<u64>().read_volatile() },
+        3
+    );
+
+    assert_eq!(unsafe { lazy::unmap_lazy(pages) }, 4);
+    assert_eq!(free_frames(), frames + 1);
+    assert_eq!(probe_read(page_addr(pages, 3)), None);
+}
+
+#[test_case]
+fn physical_memory_is_mapped_on_access() {
+    const VGA_BUFFER: u64 = 0xb8000;
+    let pages = pages();
+    let backing = Backing::Physical(PhysAddr::new(VGA_BUFFER));
+    unsafe { lazy::map_lazy(pages, backing, PageTableFlags::WRITABLE) }.unwrap();
+
+    // the last character of the first line
+    unsafe {
+        (page_addr(pages, 0) + 158u64)
+            .as_mut_ptr::<u16>()
+            .write_volatile(0x0f21)
+    };
+    let identity = memory::phys_to_virt(PhysAddr::new(VGA_BUFFER + 158));
+    assert_eq!(unsafe { identity.as_ptr::<u16>().read_volatile() }, 0x0f21);
+    assert_eq!(unsafe { lazy::unmap_lazy(pages) }, 1);
+}
+
+#[test_case]
+fn lazy_mappings_cannot_overlap() {
+    let pages = pages();
+    unsafe {
+        lazy::map_lazy(pages, Backing::Zero, PageTableFlags::WRITABLE).unwrap();
+        let overlap = PageRange {
+            start: pages.start + 8,
+            end: pages.end + 8,
+        };
+        assert_eq!(
+            lazy::map_lazy(overlap, Backing::Zero, PageTableFlags::empty()),
+            Err(lazy::LazyError::Overlap)
+        );
+        lazy::unmap_lazy(pages);
+    }
+}
+
+#[test_case]
+fn kernel_code_is_read_only() {
+    let addr = VirtAddr::new(kernel_code_is_read_only as usize as u64);
+    let byte = probe_read(addr).unwrap();
+    assert!(!probe_write(addr, byte));
+}
+
+#[test_case]
+fn kernel_rodata_is_read_only() {
+    let addr = VirtAddr::from_ptr("read-only".as_ptr());
+    let byte = probe_read(addr).unwrap();
+    assert!(!probe_write(addr, byte));
+}
@@ -0,0 +1,95 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(philos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use philos::memory::vmm::{self, VMM};
+use philos::memory::{self, Caching, FRAME_ALLOCATOR, MAPPER};
+use x86_64::structures::paging::mapper::Translate;
+use x86_64::VirtAddr;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    philos::init();
+    unsafe { memory::init(boot_info) };
+    philos::allocator::init().expect("heap initialization failed");
+    test_main();
+    philos::hlt()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    philos::test_panic_handler(info)
+}
+
+#[test_case]
+fn translation_matches_the_mapper() {
+    let value = 42u64;
+    let addr = VirtAddr::from_ptr(&value);
+    let translation = memory::translate(addr);
+    assert_eq!(
+        translation.phys_addr(),
+        MAPPER.get().unwrap().lock().translate_addr(addr)
+    );
+    assert_eq!(translation.steps().last().unwrap().level, 1);
+    memory::dump_page_tables(addr..addr + 1u64);
+    philos::serial_println!("{}", translation);
+}
+
+#[test_case]
+fn translation_stops_at_missing_entries() {
+    let pages = VMM.lock().allocate(vmm::LAZY, 1).unwrap();
+    let translation = memory::translate(pages.start.start_address());
+    assert_eq!(translation.phys_addr(), None);
+    assert_eq!(translation.page_size(), None);
+    let last = translation.steps().last().unwrap();
+    assert!(!last
+        .flags
+        .contains(x86_64::structures::paging::PageTableFlags::PRESENT));
+    VMM.lock().free(vmm::LAZY, pages).unwrap();
+}
+
+#[test_case]
+fn contiguous_mappings_are_merged() {
+    let frames = FRAME_ALLOCATOR
+        .get()
+        .unwrap()
+        .lock()
+        .allocate_contiguous(3)
+        .expect("no free frames");
+    let phys = frames.start.start_address();
+    // write-back, as in the physical memory mapping of the frames
+    let mmio = unsafe { memory::map_mmio(phys, 3 * 4096, Caching::WriteBack) }.unwrap();
+    let range = mmio.start()..mmio.start() + mmio.len();
+    let mut dump = String::new();
+    memory::inspect::write_page_tables(range, &mut dump).unwrap();
+    let run = format!(
+        "{:#018x}-{:#018x} -> {:#x}-{:#x}    3 x 4KiB ",
+        mmio.start().as_u64(),
+        mmio.start().as_u64() + 3 * 4096 - 1,
+        phys.as_u64(),
+        phys.as_u64() + 3 * 4096 - 1
+    );
+    assert!(dump.starts_with(&run), "{}", dump);
+
+    let middle = memory::translate(mmio.start() + 4096u64 + 8u64);
+    assert_eq!(middle.phys_addr(), Some(phys + 4096u64 + 8u64));
+    assert_eq!(middle.page_size(), Some(4096));
+
+    drop(mmio);
+    unsafe {
+        FRAME_ALLOCATOR
+            .get()
+            .unwrap()
+            .lock()
+            .deallocate_contiguous(frames)
+    };
+}
@@ -0,0 +1,65 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(philos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use philos::memory::{self, FRAME_ALLOCATOR};
+use x86_64::VirtAddr;
+
+static FREE_BEFORE: AtomicUsize = AtomicUsize::new(0);
+static TOTAL_BEFORE: AtomicUsize = AtomicUsize::new(0);
+static RECLAIMED: AtomicUsize = AtomicUsize::new(0);
+static RECLAIMED_AGAIN: AtomicUsize = AtomicUsize::new(0);
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    philos::init();
+    unsafe { memory::init(boot_info) };
+    let stats = FRAME_ALLOCATOR.get().unwrap().lock().stats();
+    FREE_BEFORE.store(stats.free_frames, Ordering::SeqCst);
+    TOTAL_BEFORE.store(stats.total_frames, Ordering::SeqCst);
+    let reclaimed = unsafe { memory::reclaim_boot_memory(boot_info) };
+    RECLAIMED.store(reclaimed, Ordering::SeqCst);
+    let reclaimed = unsafe { memory::reclaim_boot_memory(boot_info) };
+    RECLAIMED_AGAIN.store(reclaimed, Ordering::SeqCst);
+    test_main();
+    philos::hlt()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    philos::test_panic_handler(info)
+}
+
+#[test_case]
+fn boot_memory_is_reclaimed() {
+    let reclaimed = RECLAIMED.load(Ordering::SeqCst);
+    assert!(reclaimed > 0);
+    let stats = FRAME_ALLOCATOR.get().unwrap().lock().stats();
+    assert_eq!(
+        stats.free_frames,
+        FREE_BEFORE.load(Ordering::SeqCst) + reclaimed
+    );
+    assert_eq!(
+        stats.total_frames,
+        TOTAL_BEFORE.load(Ordering::SeqCst) + reclaimed
+    );
+}
+
+#[test_case]
+fn page_tables_are_kept() {
+    let value = 42u64;
+    let addr = VirtAddr::from_ptr(&value);
+    assert!(memory::translate(addr).phys_addr().is_some());
+    assert_eq!(unsafe { core::ptr::read_volatile(&value) }, 42);
+}
+
+#[test_case]
+fn boot_memory_is_reclaimed_once() {
+    assert_eq!(RECLAIMED_AGAIN.load(Ordering::SeqCst), 0);
+}
@@ -0,0 +1,29 @@
+#![no_std]
+#![no_main]
+#![feature(asm)]
+
+use core::panic::PanicInfo;
+use philos::{qemu, serial_print, serial_println};
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    serial_print!("segment_not_present::missing_idt_entry...\t");
+    philos::init();
+
+    // a reserved vector, its IDT entry is a valid gate that isn't present
+    unsafe {
+        asm!("int 22", options(nomem, nostack));
+    }
+
+    serial_println!("[no exception]");
+    qemu::exit(qemu::ExitCode::Failure);
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    philos::expected_panic_handler(
+        info,
+        "EXCEPTION: Segment not present (#NP, vector 11)\nerror code: 0xb2: IDT vector 22",
+    )
+}
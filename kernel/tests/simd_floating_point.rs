@@ -0,0 +1,51 @@
+#![no_std]
+#![no_main]
+#![feature(asm)]
+
+use core::panic::PanicInfo;
+use philos::{qemu, serial_print, serial_println};
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    serial_print!("simd_floating_point::unmasked_divide_by_zero...\t");
+    philos::init();
+
+    // The kernel is built without SSE, nothing else uses its registers. Execution doesn't
+    // continue after the exception, so they aren't declared as clobbered.
+    unsafe {
+        asm!(
+            // clears EM and TS, sets MP
+            "mov {0}, cr0",
+            "and {0}, ~0xc",
+            "or {0}, 0x2",
+            "mov cr0, {0}",
+            // enables SSE and its exceptions
+            "mov {0}, cr4",
+            "or {0}, 0x600",
+            "mov cr4, {0}",
+            // the default MXCSR with divide by zero unmasked
+            "push 0x1d80",
+            "ldmxcsr [rsp]",
+            "add rsp, 8",
+            // 1.0 / 0.0
+            "mov {1:e}, 0x3f800000",
+            "movd xmm0, {1:e}",
+            "xorps xmm1, xmm1",
+            "divss xmm0, xmm1",
+            out(reg) _,
+            out(reg) _,
+        );
+    }
+
+    serial_println!("[no exception]");
+    qemu::exit(qemu::ExitCode::Failure);
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    philos::expected_panic_handler(
+        info,
+        "EXCEPTION: SIMD floating-point exception (#XM, vector 19)\nerror code: none",
+    )
+}
@@ -0,0 +1,122 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(philos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use core::ptr::NonNull;
+use philos::allocator::slab::SlabCache;
+use philos::memory::{self, FRAME_ALLOCATOR};
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    philos::init();
+    unsafe { memory::init(boot_info) };
+    test_main();
+    philos::hlt()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    philos::test_panic_handler(info)
+}
+
+fn free_frames() -> usize {
+    FRAME_ALLOCATOR.get().unwrap().lock().free_frames()
+}
+
+struct Object {
+    id: u64,
+    payload: [u64; 7],
+}
+
+#[repr(align(256))]
+struct Aligned(u8);
+
+static OBJECTS: SlabCache<Object> = SlabCache::new("objects");
+static ALIGNED: SlabCache<Aligned> = SlabCache::new("aligned");
+static LARGE: SlabCache<[u8; 3000]> = SlabCache::new("large");
+
+#[test_case]
+fn objects_are_stored() {
+    let mut object = OBJECTS
+        .alloc(Object {
+            id: 42,
+            payload: [7; 7],
+        })
+        .unwrap();
+    assert_eq!(object.id, 42);
+    object.payload[3] = 3;
+    assert_eq!(object.payload.iter().sum::<u64>(), 6 * 7 + 3);
+}
+
+#[test_case]
+fn freed_objects_are_reused() {
+    // keeps the slab alive while the other object is freed
+    let first = OBJECTS.allocate().unwrap();
+    let second = OBJECTS.allocate().unwrap();
+    unsafe { OBJECTS.deallocate(second) };
+    let third = OBJECTS.allocate().unwrap();
+    assert_eq!(third, second);
+    assert_eq!(OBJECTS.stats().slabs, 1);
+    unsafe {
+        OBJECTS.deallocate(first);
+        OBJECTS.deallocate(third);
+    }
+}
+
+#[test_case]
+fn empty_slabs_are_released() {
+    let frames = free_frames();
+    let per_slab = OBJECTS.stats().objects_per_slab;
+    const COUNT: usize = 256;
+    assert!(COUNT > 2 * per_slab);
+
+    let mut objects: [Option<NonNull<Object>>; COUNT] = [None; COUNT];
+    for object in objects.iter_mut() {
+        *object = OBJECTS.allocate();
+        assert!(object.is_some());
+    }
+    let stats = OBJECTS.stats();
+    assert_eq!(stats.objects_in_use, COUNT);
+    assert_eq!(stats.slabs, (COUNT + per_slab - 1) / per_slab);
+    assert!(free_frames() < frames);
+
+    // free in an order that empties slabs at different times
+    for object in objects
+        .iter()
+        .step_by(2)
+        .chain(objects.iter().skip(1).step_by(2))
+    {
+        unsafe { OBJECTS.deallocate(object.unwrap()) };
+    }
+    let stats = OBJECTS.stats();
+    assert_eq!(stats.objects_in_use, 0);
+    assert_eq!(stats.slabs, 0);
+    assert_eq!(free_frames(), frames);
+}
+
+#[test_case]
+fn objects_are_aligned() {
+    let objects = [
+        ALIGNED.alloc(Aligned(1)).unwrap(),
+        ALIGNED.alloc(Aligned(2)).unwrap(),
+        ALIGNED.alloc(Aligned(3)).unwrap(),
+    ];
+    for object in objects.iter() {
+        assert_eq!(&**object as *const Aligned as usize % 256, 0);
+    }
+    assert_eq!(objects[2].0, 3);
+}
+
+#[test_case]
+fn large_objects_span_multiple_frames() {
+    let object = LARGE.alloc([0xa5; 3000]).unwrap();
+    assert!(object.iter().all(|&b| b == 0xa5));
+    let stats = LARGE.stats();
+    assert!(stats.objects_per_slab * stats.object_size > 4096);
+    assert_eq!(stats.slabs, 1);
+}
@@ -0,0 +1,35 @@
+#![no_std]
+#![no_main]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use philos::allocator::slab::SlabCache;
+use philos::memory;
+use philos::{qemu, serial_print, serial_println};
+
+entry_point!(main);
+
+static OBJECTS: SlabCache<u64> = SlabCache::new("objects");
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    serial_print!("slab_double_free::should_fail...\t");
+    philos::init();
+    unsafe { memory::init(boot_info) };
+
+    // the other object keeps the slab alive
+    let _other = OBJECTS.allocate().unwrap();
+    let object = OBJECTS.allocate().unwrap();
+    unsafe {
+        OBJECTS.deallocate(object);
+        OBJECTS.deallocate(object);
+    }
+
+    serial_println!("[test did not panic]");
+    qemu::exit(qemu::ExitCode::Failure);
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    philos::expected_panic_handler(info, "double free of")
+}
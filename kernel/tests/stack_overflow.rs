@@ -0,0 +1,32 @@
+#![no_std]
+#![no_main]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use philos::memory;
+use philos::serial_print;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    serial_print!("stack_overflow::kernel_stack_overflow...\t");
+
+    philos::init();
+    // replaces the interrupt stack with a guarded one and registers the kernel stack
+    unsafe { memory::init(boot_info) };
+
+    stack_overflow();
+
+    panic!("Execution continued after stack overflow");
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    philos::expected_panic_handler(info, "stack overflow in kernel stack")
+}
+
+#[allow(unconditional_recursion)]
+fn stack_overflow() {
+    stack_overflow(); // for each recursion, the return address is pushed
+    volatile::Volatile::new(&0).read(); // prevent tail recursion optimizations
+}
@@ -0,0 +1,34 @@
+#![no_std]
+#![no_main]
+#![feature(asm)]
+
+use core::panic::PanicInfo;
+use philos::{qemu, serial_print, serial_println};
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    serial_print!("stack_segment_fault::non_canonical_stack_access...\t");
+    philos::init();
+
+    // addressing based on RSP goes through SS, a non-canonical address faults with #SS
+    unsafe {
+        asm!(
+            "mov {}, qword ptr [rsp + {}]",
+            out(reg) _,
+            in(reg) 1u64 << 63,
+            options(readonly, nostack),
+        );
+    }
+
+    serial_println!("[no exception]");
+    qemu::exit(qemu::ExitCode::Failure);
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    philos::expected_panic_handler(
+        info,
+        "EXCEPTION: Stack-segment fault (#SS, vector 12)\nerror code: 0x0: not caused by a selector",
+    )
+}
@@ -0,0 +1,118 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![feature(wake_trait)]
+#![test_runner(philos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use alloc::sync::Arc;
+use alloc::task::Wake;
+use bootloader::{entry_point, BootInfo};
+use core::future::Future;
+use core::panic::PanicInfo;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll, Waker};
+use core::time::Duration;
+use futures_util::future;
+use futures_util::stream::StreamExt;
+use philos::task::simple_executor::SimpleExecutor;
+use philos::task::timer::{self, Elapsed};
+use philos::task::Task;
+use philos::time;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    philos::init();
+    unsafe { philos::memory::init(boot_info) };
+    philos::allocator::init().expect("heap initialization failed");
+    test_main();
+    philos::hlt()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    philos::test_panic_handler(info)
+}
+
+fn run(future: impl Future<Output = ()> + 'static) {
+    let mut executor = SimpleExecutor::new();
+    executor.spawn(Task::new(future));
+    executor.run();
+}
+
+#[test_case]
+fn ticks_advance() {
+    let start = time::ticks();
+    x86_64::instructions::hlt();
+    assert!(time::ticks() > start);
+}
+
+#[test_case]
+fn durations_round_up_to_ticks() {
+    assert_eq!(time::duration_to_ticks(Duration::from_secs(0)), 0);
+    assert_eq!(time::duration_to_ticks(Duration::from_nanos(1)), 1);
+    let ticks = time::duration_to_ticks(Duration::from_secs(1));
+    assert!(time::ticks_to_duration(ticks) >= Duration::from_secs(1));
+    assert!(time::ticks_to_duration(ticks - 1) < Duration::from_secs(1));
+}
+
+#[test_case]
+fn sleep_lasts_at_least_its_duration() {
+    run(async {
+        let start = time::uptime();
+        timer::sleep(Duration::from_millis(20)).await;
+        assert!(time::uptime() - start >= Duration::from_millis(20));
+    });
+}
+
+struct Flag(AtomicBool);
+
+impl Wake for Flag {
+    fn wake(self: Arc<Self>) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+#[test_case]
+fn sleep_is_woken_by_the_timer_interrupt() {
+    let flag = Arc::new(Flag(AtomicBool::new(false)));
+    let waker = Waker::from(flag.clone());
+    let mut context = Context::from_waker(&waker);
+    let mut sleep = timer::sleep(Duration::from_millis(5));
+    assert_eq!(Pin::new(&mut sleep).poll(&mut context), Poll::Pending);
+    while !flag.0.load(Ordering::SeqCst) {
+        x86_64::instructions::hlt();
+    }
+    assert_eq!(Pin::new(&mut sleep).poll(&mut context), Poll::Ready(()));
+}
+
+#[test_case]
+fn interval_yields_every_period() {
+    run(async {
+        let period = Duration::from_millis(10);
+        let mut interval = timer::interval(period);
+        let first = interval.next().await.unwrap();
+        let second = interval.next().await.unwrap();
+        assert_eq!(second - first, time::duration_to_ticks(period));
+    });
+}
+
+#[test_case]
+fn timeout_completes_with_the_future() {
+    run(async {
+        let result = timer::timeout(Duration::from_millis(10), async { 42 }).await;
+        assert_eq!(result, Ok(42));
+    });
+}
+
+#[test_case]
+fn timeout_elapses() {
+    run(async {
+        let result = timer::timeout(Duration::from_millis(10), future::pending::<()>()).await;
+        assert_eq!(result, Err(Elapsed));
+    });
+}
@@ -0,0 +1,81 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(philos::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+use philos::memory::vmm::{Vmm, VmmError};
+use x86_64::structures::paging::page::PageRange;
+use x86_64::structures::paging::Page;
+use x86_64::VirtAddr;
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    test_main();
+    philos::hlt()
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    philos::test_panic_handler(info)
+}
+
+fn pages(start: u64, count: u64) -> PageRange {
+    let start = Page::containing_address(VirtAddr::new(start));
+    Page::range(start, start + count)
+}
+
+#[test_case]
+fn overlapping_regions_are_rejected() {
+    let mut vmm = Vmm::new();
+    vmm.reserve("a", pages(0x10_0000, 16)).unwrap();
+    assert_eq!(
+        vmm.reserve("b", pages(0x10_f000, 16)),
+        Err(VmmError::Overlap("a"))
+    );
+    assert_eq!(
+        vmm.reserve("b", pages(0x0f_f000, 2)),
+        Err(VmmError::Overlap("a"))
+    );
+    vmm.reserve("b", pages(0x11_0000, 16)).unwrap();
+}
+
+#[test_case]
+fn freed_ranges_are_reused() {
+    let mut vmm = Vmm::new();
+    vmm.reserve("a", pages(0x10_0000, 4)).unwrap();
+    let first = vmm.allocate("a", 2).unwrap();
+    let second = vmm.allocate("a", 2).unwrap();
+    assert_eq!(vmm.allocate("a", 1), Err(VmmError::OutOfSpace));
+
+    vmm.free("a", first).unwrap();
+    assert_eq!(vmm.allocate("a", 2), Ok(first));
+
+    vmm.free("a", first).unwrap();
+    vmm.free("a", second).unwrap();
+    // both ranges were merged back together
+    assert_eq!(vmm.allocate("a", 4), Ok(pages(0x10_0000, 4)));
+}
+
+#[test_case]
+fn invalid_frees_are_rejected() {
+    let mut vmm = Vmm::new();
+    vmm.reserve("a", pages(0x10_0000, 4)).unwrap();
+    let range = vmm.allocate("a", 2).unwrap();
+    assert_eq!(vmm.free("b", range), Err(VmmError::UnknownRegion));
+    assert_eq!(
+        vmm.free("a", pages(0x20_0000, 1)),
+        Err(VmmError::InvalidRange)
+    );
+    vmm.free("a", range).unwrap();
+    assert_eq!(vmm.free("a", range), Err(VmmError::InvalidRange));
+}
+
+#[test_case]
+fn aligned_allocation() {
+    let mut vmm = Vmm::new();
+    vmm.reserve("a", pages(0x1000, 1024)).unwrap();
+    let range = vmm.allocate_aligned("a", 1, 0x20_0000).unwrap();
+    assert!(range.start.start_address().is_aligned(0x20_0000u64));
+}
@@ -0,0 +1,45 @@
+#![no_std]
+#![no_main]
+#![feature(asm)]
+
+use core::panic::PanicInfo;
+use philos::{qemu, serial_print, serial_println};
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    serial_print!("x87_floating_point::unmasked_divide_by_zero...\t");
+    philos::init();
+
+    // reported as #MF rather than on IRQ 13 with CR0.NE, when the next x87 instruction waits
+    unsafe {
+        asm!(
+            // clears EM and TS, sets MP and NE
+            "mov {0}, cr0",
+            "and {0}, ~0xc",
+            "or {0}, 0x22",
+            "mov cr0, {0}",
+            "fninit",
+            // the default control word with divide by zero unmasked
+            "push 0x037b",
+            "fldcw [rsp]",
+            "add rsp, 8",
+            "fld1",
+            "fldz",
+            "fdivp st(1), st",
+            "fwait",
+            out(reg) _,
+        );
+    }
+
+    serial_println!("[no exception]");
+    qemu::exit(qemu::ExitCode::Failure);
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    philos::expected_panic_handler(
+        info,
+        "EXCEPTION: x87 floating-point exception (#MF, vector 16)\nerror code: none",
+    )
+}
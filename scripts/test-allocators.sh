@@ -0,0 +1,9 @@
+#!/bin/sh
+# Runs the heap allocation tests against every global allocator, stopping at the first failure.
+set -e
+cd "$(dirname "$0")/../kernel"
+
+for allocator in bump fixed free-list linked-list; do
+  echo "Testing alloc-$allocator"
+  cargo test --no-default-features --features "alloc-$allocator" --test heap_allocation
+done